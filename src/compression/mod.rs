@@ -0,0 +1,4 @@
+// Compression formats used for the files inside of a ROM, as opposed to the
+// ones in `container`, which the whole disc image is stored in.
+
+pub mod yaz0;
@@ -0,0 +1,275 @@
+// Yaz0, the LZ77 variant that a lot of Nintendo's games compress their files
+// with (often, but not always, with a `.szs` extension). The header is the
+// magic, the decompressed size, and 8 unused bytes. After that, each group
+// starts with a byte whose bits say, from the highest down, whether the next
+// 8 chunks are a literal byte or a copy of data that was already decompressed.
+// A copy is 2 bytes, with the size in the top 4 bits (plus 2), and the
+// distance back minus 1 in the other 12. If the size bits are 0, a third byte
+// has the size minus 0x12.
+
+use std::cmp::min;
+use std::io;
+
+use byteorder::{BigEndian, ByteOrder};
+
+pub const YAZ0_MAGIC: &[u8; 4] = b"Yaz0";
+pub const YAZ0_HEADER_SIZE: usize = 0x10;
+
+const WINDOW_SIZE: usize = 0x1000;
+const MIN_MATCH_SIZE: usize = 3;
+const MAX_MATCH_SIZE: usize = 0xFF + 0x12;
+
+const HASH_BITS: u32 = 15;
+
+pub fn is_yaz0(data: &[u8]) -> bool {
+    data.len() >= YAZ0_HEADER_SIZE && &data[..4] == YAZ0_MAGIC
+}
+
+// The size of the data once it's decompressed, from the header
+pub fn decompressed_size(data: &[u8]) -> Option<usize> {
+    if is_yaz0(data) {
+        Some(BigEndian::read_u32(&data[4..8]) as usize)
+    } else {
+        None
+    }
+}
+
+pub fn decompress(data: &[u8]) -> io::Result<Vec<u8>> {
+    let size = decompressed_size(data)
+        .ok_or_else(|| invalid_data("Not a Yaz0 file"))?;
+    let mut out = Vec::with_capacity(size);
+    let mut pos = YAZ0_HEADER_SIZE;
+
+    let truncated = || invalid_data("The Yaz0 data ends early");
+
+    while out.len() < size {
+        let code = *data.get(pos).ok_or_else(truncated)?;
+        pos += 1;
+
+        for bit in (0..8).rev() {
+            if out.len() >= size { break }
+
+            if code & (1 << bit) != 0 {
+                out.push(*data.get(pos).ok_or_else(truncated)?);
+                pos += 1;
+                continue
+            }
+
+            let b = data.get(pos..pos + 2).ok_or_else(truncated)?;
+            let distance = (((b[0] as usize & 0xF) << 8) | b[1] as usize) + 1;
+            let count = match b[0] >> 4 {
+                0 => {
+                    let n = *data.get(pos + 2).ok_or_else(truncated)?;
+                    pos += 1;
+                    n as usize + 0x12
+                },
+                n => n as usize + 2,
+            };
+            pos += 2;
+
+            if distance > out.len() {
+                return Err(invalid_data("The Yaz0 data refers to data before the start"));
+            }
+            // Data past the decompressed size is just dropped
+            let count = min(count, size - out.len());
+            let start = out.len() - distance;
+            if distance >= count {
+                out.extend_from_within(start..start + count);
+            } else {
+                // The copy overlaps what it's writing, which repeats the data
+                for i in start..start + count {
+                    let b = out[i];
+                    out.push(b);
+                }
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+// This always takes the longest match in the window, except when skipping a
+// byte gives one that's at least 2 bytes longer.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(YAZ0_HEADER_SIZE + data.len() + data.len() / 8 + 1);
+    out.extend_from_slice(YAZ0_MAGIC);
+    let mut size = [0; 4];
+    BigEndian::write_u32(&mut size, data.len() as u32);
+    out.extend_from_slice(&size);
+    out.extend_from_slice(&[0; 8]);
+
+    let mut finder = MatchFinder::new(data);
+    let mut pos = 0;
+    let mut code_pos = out.len();
+    let mut bit = 8;
+    // The match found after a byte was skipped, so it isn't searched for twice
+    let mut next_match = None;
+
+    while pos < data.len() {
+        if bit == 8 {
+            code_pos = out.len();
+            out.push(0);
+            bit = 0;
+        }
+
+        // A match that was found by skipping a byte is always used, without
+        // looking ahead again
+        let (count, mut distance) = match next_match.take() {
+            Some(m) => m,
+            None => {
+                let (count, distance) = finder.find(pos);
+                if count >= MIN_MATCH_SIZE {
+                    let (next_count, next_distance) = finder.find(pos + 1);
+                    if next_count >= count + 2 {
+                        next_match = Some((next_count, next_distance));
+                        (0, 0)
+                    } else {
+                        (count, distance)
+                    }
+                } else {
+                    (count, distance)
+                }
+            },
+        };
+
+        if count < MIN_MATCH_SIZE {
+            out[code_pos] |= 0x80 >> bit;
+            out.push(data[pos]);
+            pos += 1;
+        } else {
+            distance -= 1;
+            if count >= 0x12 {
+                out.push((distance >> 8) as u8);
+                out.push(distance as u8);
+                out.push((count - 0x12) as u8);
+            } else {
+                out.push((((count - 2) << 4) | (distance >> 8)) as u8);
+                out.push(distance as u8);
+            }
+            pos += count;
+        }
+        bit += 1;
+    }
+
+    out
+}
+
+// Finds the longest match in the window with hash chains of every position
+// that starts with the same 3 bytes, closest first.
+struct MatchFinder<'a> {
+    data: &'a [u8],
+    // The last position with each hash, and for each position in the
+    // window, the one before it with the same hash
+    head: Vec<Option<usize>>,
+    prev: Vec<Option<usize>>,
+    // Every position before this is in the chains
+    inserted: usize,
+}
+
+impl<'a> MatchFinder<'a> {
+    fn new(data: &'a [u8]) -> MatchFinder<'a> {
+        MatchFinder {
+            data,
+            head: vec![None; 1 << HASH_BITS],
+            prev: vec![None; WINDOW_SIZE],
+            inserted: 0,
+        }
+    }
+
+    fn hash(&self, pos: usize) -> usize {
+        let d = &self.data[pos..pos + MIN_MATCH_SIZE];
+        let n = (d[0] as u32) << 16 | (d[1] as u32) << 8 | d[2] as u32;
+        (n.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+    }
+
+    // Returns the size of the longest match for `pos`, and how far back it
+    // is. The size is less than `MIN_MATCH_SIZE` if there isn't one.
+    fn find(&mut self, pos: usize) -> (usize, usize) {
+        if pos + MIN_MATCH_SIZE > self.data.len() {
+            return (0, 0);
+        }
+
+        while self.inserted < pos {
+            let h = self.hash(self.inserted);
+            self.prev[self.inserted % WINDOW_SIZE] = self.head[h];
+            self.head[h] = Some(self.inserted);
+            self.inserted += 1;
+        }
+
+        let max_size = min(MAX_MATCH_SIZE, self.data.len() - pos);
+        let target = &self.data[pos..pos + max_size];
+        let (mut best_size, mut best_distance) = (0, 0);
+
+        let mut candidate = self.head[self.hash(pos)];
+        while let Some(c) = candidate {
+            if pos - c > WINDOW_SIZE { break }
+
+            let size = self.data[c..].iter().zip(target)
+                .take_while(|(a, b)| a == b)
+                .count();
+            if size > best_size {
+                best_size = size;
+                best_distance = pos - c;
+                if size == max_size { break }
+            }
+            candidate = self.prev[c % WINDOW_SIZE];
+        }
+
+        (best_size, best_distance)
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(data: &[u8]) {
+        let compressed = compress(data);
+        assert!(is_yaz0(&compressed));
+        assert_eq!(decompressed_size(&compressed), Some(data.len()));
+        assert_eq!(decompress(&compressed).unwrap(), data);
+    }
+
+    #[test]
+    fn round_trip_empty() {
+        round_trip(&[]);
+        round_trip(b"a");
+        round_trip(b"ab");
+    }
+
+    #[test]
+    fn round_trip_long_run() {
+        // Longer than the longest match, so the run takes several copies
+        let data = vec![0x55; MAX_MATCH_SIZE * 3 + 7];
+        round_trip(&data);
+        assert!(compress(&data).len() < YAZ0_HEADER_SIZE + 16);
+    }
+
+    #[test]
+    fn round_trip_incompressible() {
+        // A simple LCG, so the data doesn't repeat
+        let mut x: u32 = 12345;
+        let data: Vec<u8> = (0..10000).map(|_| {
+            x = x.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (x >> 16) as u8
+        }).collect();
+        round_trip(&data);
+    }
+
+    #[test]
+    fn round_trip_text() {
+        let data = b"Yaz0 repeats: abcabcabcabc, then the same words again. \
+            Yaz0 repeats: abcabcabcabc, then the same words again.".repeat(200);
+        round_trip(&data);
+    }
+
+    #[test]
+    fn decompress_truncated() {
+        let compressed = compress(&[1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        assert!(decompress(&compressed[..compressed.len() - 1]).is_err());
+    }
+}
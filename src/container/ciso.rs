@@ -0,0 +1,204 @@
+// The CISO (compact ISO) format used by many USB loaders. The header is a
+// block size followed by a map with one byte per block, which says whether
+// the block is stored in the file. Blocks that aren't stored are all zeros.
+// The stored blocks follow the header, in order.
+
+use std::cmp::min;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use container::seek_position;
+
+pub const CISO_MAGIC: &[u8; 4] = b"CISO";
+pub const CISO_HEADER_SIZE: usize = 0x8000;
+pub const CISO_MAP_SIZE: usize = CISO_HEADER_SIZE - 8;
+
+// 2MiB, which is what most tools that create CISO files use
+pub const DEFAULT_CISO_BLOCK_SIZE: u32 = 0x20_0000;
+
+pub struct CisoReader<R> {
+    reader: R,
+    block_size: u64,
+    // The offset in the file of each block, or `None` if it isn't stored
+    block_offsets: Vec<Option<u64>>,
+    size: u64,
+    position: u64,
+}
+
+impl<R: Read + Seek> CisoReader<R> {
+    pub fn new(mut reader: R) -> io::Result<CisoReader<R>> {
+        reader.seek(SeekFrom::Start(0))?;
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != CISO_MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a CISO file"));
+        }
+
+        let block_size = reader.read_u32::<LittleEndian>()? as u64;
+        if block_size == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid CISO block size"));
+        }
+
+        let mut map = vec![0; CISO_MAP_SIZE];
+        reader.read_exact(&mut map)?;
+
+        let mut next_offset = CISO_HEADER_SIZE as u64;
+        let block_offsets: Vec<_> = map.iter().map(|&used| {
+            if used == 1 {
+                next_offset += block_size;
+                Some(next_offset - block_size)
+            } else {
+                None
+            }
+        }).collect();
+
+        let block_count = block_offsets.iter().rposition(Option::is_some)
+            .map(|i| i + 1)
+            .unwrap_or(0);
+
+        Ok(CisoReader {
+            reader,
+            block_size,
+            block_offsets,
+            size: block_count as u64 * block_size,
+            position: 0,
+        })
+    }
+}
+
+impl<R: Read + Seek> Read for CisoReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.size || buf.is_empty() {
+            return Ok(0);
+        }
+
+        let index = (self.position / self.block_size) as usize;
+        let start = self.position % self.block_size;
+        let len = min(buf.len() as u64, self.block_size - start) as usize;
+
+        let bytes_read = match self.block_offsets[index] {
+            Some(offset) => {
+                self.reader.seek(SeekFrom::Start(offset + start))?;
+                self.reader.read(&mut buf[..len])?
+            },
+            None => {
+                for b in &mut buf[..len] { *b = 0 }
+                len
+            },
+        };
+
+        self.position += bytes_read as u64;
+        Ok(bytes_read)
+    }
+}
+
+impl<R: Read + Seek> Seek for CisoReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.position = seek_position(self.position, self.size, pos)?;
+        Ok(self.position)
+    }
+}
+
+// Writes a CISO file. Data is written with `Write` like a regular ISO,
+// but gaps should be passed to `skip`, so blocks that only contain
+// skipped bytes don't need to be stored. `finish` must be called
+// once everything has been written, to fill in the header.
+pub struct CisoWriter<W> {
+    writer: W,
+    block_size: u64,
+    map: Vec<u8>,
+    // The contents of the current block, and whether any data has been
+    // written to it (skipping doesn't count).
+    block: Vec<u8>,
+    block_used: bool,
+}
+
+impl<W: Write + Seek> CisoWriter<W> {
+    pub fn new(mut writer: W, block_size: u32) -> io::Result<CisoWriter<W>> {
+        if block_size == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid CISO block size"));
+        }
+
+        // The header is filled in by `finish`
+        writer.seek(SeekFrom::Start(0))?;
+        writer.write_all(&[0; CISO_HEADER_SIZE])?;
+
+        Ok(CisoWriter {
+            writer,
+            block_size: block_size as u64,
+            map: Vec::new(),
+            block: Vec::with_capacity(block_size as usize),
+            block_used: false,
+        })
+    }
+
+    pub fn skip(&mut self, mut count: u64) -> io::Result<()> {
+        while count > 0 {
+            let space_left = self.block_size - self.block.len() as u64;
+            if self.block.is_empty() && count >= self.block_size {
+                // Skip entire blocks without buffering anything
+                let blocks = count / self.block_size;
+                self.map.resize(self.map.len() + blocks as usize, 0);
+                count -= blocks * self.block_size;
+            } else {
+                let n = min(count, space_left);
+                let new_len = self.block.len() + n as usize;
+                self.block.resize(new_len, 0);
+                count -= n;
+                self.end_block_if_full()?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.block.is_empty() {
+            let remaining = self.block_size - self.block.len() as u64;
+            self.skip(remaining)?;
+        }
+
+        if self.map.len() > CISO_MAP_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Too many blocks for a CISO file, try a larger block size",
+            ));
+        }
+
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(CISO_MAGIC)?;
+        self.writer.write_u32::<LittleEndian>(self.block_size as u32)?;
+        self.writer.write_all(&self.map)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn end_block_if_full(&mut self) -> io::Result<()> {
+        if self.block.len() as u64 == self.block_size {
+            if self.block_used {
+                self.writer.write_all(&self.block)?;
+            }
+            self.map.push(self.block_used as u8);
+            self.block.clear();
+            self.block_used = false;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> Write for CisoWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let space_left = (self.block_size as usize) - self.block.len();
+        let n = min(buf.len(), space_left);
+        if n > 0 {
+            self.block.extend_from_slice(&buf[..n]);
+            self.block_used = true;
+            self.end_block_if_full()?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
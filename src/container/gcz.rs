@@ -0,0 +1,146 @@
+// Dolphin's GCZ format, as implemented in Source/Core/DiscIO/CompressedBlob.cpp.
+// Every value in the header and block table is little endian.
+
+use std::cmp::min;
+use std::io::{self, Read, Seek, SeekFrom};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use flate2::read::ZlibDecoder;
+
+use container::seek_position;
+
+pub const GCZ_MAGIC: u32 = 0xB10B_C001;
+pub const GCZ_HEADER_SIZE: u64 = 0x20;
+
+// Blocks with this bit set in their pointer are stored uncompressed
+const UNCOMPRESSED_FLAG: u64 = 1 << 63;
+
+#[derive(Debug)]
+pub struct GczHeader {
+    pub sub_type: u32,
+    pub compressed_data_size: u64,
+    pub data_size: u64,
+    pub block_size: u32,
+    pub block_count: u32,
+}
+
+impl GczHeader {
+    pub fn new(mut reader: impl Read + Seek) -> io::Result<GczHeader> {
+        reader.seek(SeekFrom::Start(0))?;
+        if reader.read_u32::<LittleEndian>()? != GCZ_MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a GCZ file"));
+        }
+        let header = GczHeader {
+            sub_type: reader.read_u32::<LittleEndian>()?,
+            compressed_data_size: reader.read_u64::<LittleEndian>()?,
+            data_size: reader.read_u64::<LittleEndian>()?,
+            block_size: reader.read_u32::<LittleEndian>()?,
+            block_count: reader.read_u32::<LittleEndian>()?,
+        };
+        if header.block_size == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid GCZ block size"));
+        }
+        Ok(header)
+    }
+}
+
+// Presents the uncompressed disc image stored in a GCZ file.
+pub struct GczReader<R> {
+    reader: R,
+    header: GczHeader,
+    block_pointers: Vec<u64>,
+    data_offset: u64,
+    position: u64,
+    // The index and contents of the most recently decompressed block
+    current_block: Option<(u64, Vec<u8>)>,
+}
+
+impl<R: Read + Seek> GczReader<R> {
+    pub fn new(mut reader: R) -> io::Result<GczReader<R>> {
+        let header = GczHeader::new(&mut reader)?;
+
+        let mut block_pointers = Vec::with_capacity(header.block_count as usize);
+        for _ in 0..header.block_count {
+            block_pointers.push(reader.read_u64::<LittleEndian>()?);
+        }
+
+        // The pointers are followed by a hash for each block, then the blocks
+        let data_offset = GCZ_HEADER_SIZE + 12 * header.block_count as u64;
+
+        Ok(GczReader {
+            reader,
+            header,
+            block_pointers,
+            data_offset,
+            position: 0,
+            current_block: None,
+        })
+    }
+
+    pub fn header(&self) -> &GczHeader {
+        &self.header
+    }
+
+    fn load_block(&mut self, index: u64) -> io::Result<()> {
+        if self.current_block.as_ref().map(|b| b.0) == Some(index) {
+            return Ok(());
+        }
+
+        let pointer = self.block_pointers.get(index as usize).cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "GCZ block out of range"))?;
+        let start = pointer & !UNCOMPRESSED_FLAG;
+        let end = match self.block_pointers.get(index as usize + 1) {
+            Some(p) => p & !UNCOMPRESSED_FLAG,
+            None => self.header.compressed_data_size,
+        };
+        if end < start {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid GCZ block pointer"));
+        }
+
+        self.reader.seek(SeekFrom::Start(self.data_offset + start))?;
+        let raw = (&mut self.reader).take(end - start);
+
+        let block_size = self.header.block_size as usize;
+        let mut block = Vec::with_capacity(block_size);
+        if pointer & UNCOMPRESSED_FLAG != 0 {
+            raw.take(block_size as u64).read_to_end(&mut block)?;
+        } else {
+            ZlibDecoder::new(raw).take(block_size as u64).read_to_end(&mut block)?;
+        }
+        // Pad short blocks so every block is the same size
+        block.resize(block_size, 0);
+
+        self.current_block = Some((index, block));
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Read for GczReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.header.data_size || buf.is_empty() {
+            return Ok(0);
+        }
+
+        let block_size = self.header.block_size as u64;
+        let index = self.position / block_size;
+        self.load_block(index)?;
+
+        let block = &self.current_block.as_ref().unwrap().1;
+        let start = (self.position % block_size) as usize;
+        let len = min(
+            min(buf.len(), block.len() - start) as u64,
+            self.header.data_size - self.position,
+        ) as usize;
+        buf[..len].copy_from_slice(&block[start..start + len]);
+
+        self.position += len as u64;
+        Ok(len)
+    }
+}
+
+impl<R: Read + Seek> Seek for GczReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.position = seek_position(self.position, self.header.data_size, pos)?;
+        Ok(self.position)
+    }
+}
@@ -0,0 +1,120 @@
+// Readers for the compressed and container formats that GameCube disc images
+// are commonly stored in. Each reader presents the plain ISO data through
+// `Read + Seek`, so everything else can treat them like a regular ISO.
+
+pub mod ciso;
+pub mod gcz;
+pub mod wia;
+
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+use self::ciso::{CisoReader, CISO_MAGIC};
+use self::gcz::{GczReader, GCZ_MAGIC};
+use self::wia::{WiaReader, RVZ_MAGIC, WIA_MAGIC};
+use sections::tgc::{TGCReader, TGC_MAGIC};
+
+pub trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ContainerFormat {
+    Iso,
+    Gcz,
+    Ciso,
+    Wia,
+    Rvz,
+    Tgc,
+}
+
+impl ContainerFormat {
+    // Looks at the magic bytes at the start of `reader` to figure out what
+    // format it's in. Anything unrecognized is assumed to be a plain ISO.
+    pub fn detect(mut reader: impl Read + Seek) -> io::Result<ContainerFormat> {
+        reader.seek(SeekFrom::Start(0))?;
+        let mut magic = [0; 4];
+        let bytes_read = reader.read(&mut magic)?;
+        reader.seek(SeekFrom::Start(0))?;
+
+        if bytes_read < magic.len() {
+            return Ok(ContainerFormat::Iso);
+        }
+
+        match &magic {
+            CISO_MAGIC => return Ok(ContainerFormat::Ciso),
+            WIA_MAGIC => return Ok(ContainerFormat::Wia),
+            RVZ_MAGIC => return Ok(ContainerFormat::Rvz),
+            _ => {},
+        }
+
+        if BigEndian::read_u32(&magic) == TGC_MAGIC {
+            return Ok(ContainerFormat::Tgc);
+        }
+
+        Ok(match LittleEndian::read_u32(&magic) {
+            GCZ_MAGIC => ContainerFormat::Gcz,
+            _ => ContainerFormat::Iso,
+        })
+    }
+
+    pub fn open<R>(self, mut reader: R) -> io::Result<Box<dyn ReadSeek>>
+    where
+        R: Read + Seek + 'static,
+    {
+        Ok(match self {
+            ContainerFormat::Iso => Box::new(reader),
+            ContainerFormat::Gcz => Box::new(GczReader::new(reader)?),
+            ContainerFormat::Ciso => Box::new(CisoReader::new(reader)?),
+            ContainerFormat::Wia | ContainerFormat::Rvz =>
+                Box::new(WiaReader::new(reader)?),
+            ContainerFormat::Tgc => {
+                let size = reader.seek(SeekFrom::End(0))?;
+                Box::new(TGCReader::new(reader, 0, size)?)
+            },
+        })
+    }
+}
+
+impl fmt::Display for ContainerFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ContainerFormat::Iso => "ISO",
+            ContainerFormat::Gcz => "GCZ",
+            ContainerFormat::Ciso => "CISO",
+            ContainerFormat::Wia => "WIA",
+            ContainerFormat::Rvz => "RVZ",
+            ContainerFormat::Tgc => "TGC",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Opens a disc image in any of the supported formats.
+pub fn open_disc_image<R>(mut reader: R) -> io::Result<(ContainerFormat, Box<dyn ReadSeek>)>
+where
+    R: Read + Seek + 'static,
+{
+    let format = ContainerFormat::detect(&mut reader)?;
+    format.open(reader).map(|r| (format, r))
+}
+
+// Computes the position after a seek, for readers that keep track of their
+// own position within `size` bytes of uncompressed data.
+pub fn seek_position(current: u64, size: u64, pos: SeekFrom) -> io::Result<u64> {
+    let new_pos = match pos {
+        SeekFrom::Start(p) => p as i64,
+        SeekFrom::End(p) => size as i64 + p,
+        SeekFrom::Current(p) => current as i64 + p,
+    };
+    if new_pos < 0 {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "invalid seek to a negative position",
+        ))
+    } else {
+        Ok(new_pos as u64)
+    }
+}
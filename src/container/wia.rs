@@ -0,0 +1,520 @@
+// Dolphin's WIA and RVZ formats, as described in docs/WiaAndRvz.md in the
+// Dolphin repository. Only GameCube discs are supported, so there are no
+// partitions to deal with, just the raw data entries.
+// Every value in these formats is big endian.
+
+use std::cmp::min;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+
+use byteorder::{BigEndian, ByteOrder, ReadBytesExt};
+use bzip2::read::BzDecoder;
+use lzma_rs::decompress::{Options, UnpackedSize};
+use ruzstd::StreamingDecoder;
+
+use container::seek_position;
+
+pub const WIA_MAGIC: &[u8; 4] = b"WIA\x01";
+pub const RVZ_MAGIC: &[u8; 4] = b"RVZ\x01";
+
+const FILE_HEAD_SIZE: u64 = 0x48;
+const DISC_HEADER_SIZE: usize = 0x80;
+const RAW_DATA_ENTRY_SIZE: usize = 0x18;
+const WIA_GROUP_ENTRY_SIZE: usize = 0x08;
+const RVZ_GROUP_ENTRY_SIZE: usize = 0x0C;
+
+const GAMECUBE_DISC_TYPE: u32 = 1;
+
+// Raw data entries start at a multiple of this, even if
+// their offset says otherwise
+const WII_BLOCK_SIZE: u64 = 0x8000;
+
+// The hash at the end of purge compressed data
+const SHA1_SIZE: usize = 20;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum WiaCompression {
+    None,
+    Purge,
+    Bzip2,
+    Lzma,
+    Lzma2,
+    Zstd,
+}
+
+impl WiaCompression {
+    fn new(n: u32) -> io::Result<WiaCompression> {
+        use self::WiaCompression::*;
+        Ok(match n {
+            0 => None,
+            1 => Purge,
+            2 => Bzip2,
+            3 => Lzma,
+            4 => Lzma2,
+            5 => Zstd,
+            _ => return Err(invalid_data(format!("Unknown WIA compression type: {}", n))),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct WiaHeader {
+    pub is_rvz: bool,
+    pub version: u32,
+    pub iso_file_size: u64,
+    pub disc_type: u32,
+    pub compression: WiaCompression,
+    pub chunk_size: u32,
+    pub disc_header: Vec<u8>,
+    // The properties needed by some compression methods, like LZMA
+    pub compressor_data: Vec<u8>,
+    raw_data_count: u32,
+    raw_data_offset: u64,
+    raw_data_size: u32,
+    group_count: u32,
+    group_offset: u64,
+    group_size: u32,
+}
+
+impl WiaHeader {
+    pub fn new(mut reader: impl Read + Seek) -> io::Result<WiaHeader> {
+        reader.seek(SeekFrom::Start(0))?;
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        let is_rvz = match &magic {
+            WIA_MAGIC => false,
+            RVZ_MAGIC => true,
+            _ => return Err(invalid_data("Not a WIA or RVZ file")),
+        };
+
+        let version = reader.read_u32::<BigEndian>()?;
+        reader.seek(SeekFrom::Start(0x24))?;
+        let iso_file_size = reader.read_u64::<BigEndian>()?;
+
+        reader.seek(SeekFrom::Start(FILE_HEAD_SIZE))?;
+        let disc_type = reader.read_u32::<BigEndian>()?;
+        let compression = WiaCompression::new(reader.read_u32::<BigEndian>()?)?;
+        let _compression_level = reader.read_i32::<BigEndian>()?;
+        let chunk_size = reader.read_u32::<BigEndian>()?;
+
+        let mut disc_header = vec![0; DISC_HEADER_SIZE];
+        reader.read_exact(&mut disc_header)?;
+
+        // Partition information, which GameCube discs don't use
+        reader.seek(SeekFrom::Current(4 + 4 + 8 + SHA1_SIZE as i64))?;
+
+        let raw_data_count = reader.read_u32::<BigEndian>()?;
+        let raw_data_offset = reader.read_u64::<BigEndian>()?;
+        let raw_data_size = reader.read_u32::<BigEndian>()?;
+        let group_count = reader.read_u32::<BigEndian>()?;
+        let group_offset = reader.read_u64::<BigEndian>()?;
+        let group_size = reader.read_u32::<BigEndian>()?;
+
+        let compressor_data_len = reader.read_u8()? as usize;
+        let mut compressor_data = vec![0; 7];
+        reader.read_exact(&mut compressor_data)?;
+        compressor_data.truncate(compressor_data_len);
+
+        if disc_type != GAMECUBE_DISC_TYPE {
+            return Err(invalid_data("Only GameCube WIA and RVZ files are supported"));
+        }
+        if chunk_size == 0 {
+            return Err(invalid_data("Invalid WIA chunk size"));
+        }
+
+        Ok(WiaHeader {
+            is_rvz,
+            version,
+            iso_file_size,
+            disc_type,
+            compression,
+            chunk_size,
+            disc_header,
+            compressor_data,
+            raw_data_count,
+            raw_data_offset,
+            raw_data_size,
+            group_count,
+            group_offset,
+            group_size,
+        })
+    }
+}
+
+#[derive(Debug)]
+struct RawDataEntry {
+    // Already aligned down to a multiple of `WII_BLOCK_SIZE`
+    data_offset: u64,
+    data_size: u64,
+    group_index: u32,
+    group_count: u32,
+}
+
+#[derive(Debug)]
+struct GroupEntry {
+    data_offset: u64,
+    data_size: u32,
+    is_compressed: bool,
+    // Only used by RVZ. If this isn't zero, the data is packed
+    // (see `rvz_unpack`) and this is its size once decompressed.
+    packed_size: u32,
+}
+
+pub struct WiaReader<R> {
+    reader: R,
+    header: WiaHeader,
+    raw_data: Vec<RawDataEntry>,
+    groups: Vec<GroupEntry>,
+    position: u64,
+    // The index and contents of the most recently decompressed group
+    current_group: Option<(usize, Vec<u8>)>,
+}
+
+impl<R: Read + Seek> WiaReader<R> {
+    pub fn new(mut reader: R) -> io::Result<WiaReader<R>> {
+        let header = WiaHeader::new(&mut reader)?;
+
+        let raw_data_table = read_table(
+            &mut reader,
+            &header,
+            header.raw_data_offset,
+            header.raw_data_size as u64,
+            header.raw_data_count as usize * RAW_DATA_ENTRY_SIZE,
+        )?;
+        let raw_data = raw_data_table.chunks(RAW_DATA_ENTRY_SIZE).map(|e| {
+            let offset = BigEndian::read_u64(&e[0..8]);
+            let size = BigEndian::read_u64(&e[8..16]);
+            let skipped = offset % WII_BLOCK_SIZE;
+            RawDataEntry {
+                data_offset: offset - skipped,
+                data_size: size + skipped,
+                group_index: BigEndian::read_u32(&e[16..20]),
+                group_count: BigEndian::read_u32(&e[20..24]),
+            }
+        }).collect();
+
+        let group_entry_size = if header.is_rvz {
+            RVZ_GROUP_ENTRY_SIZE
+        } else {
+            WIA_GROUP_ENTRY_SIZE
+        };
+        let group_table = read_table(
+            &mut reader,
+            &header,
+            header.group_offset,
+            header.group_size as u64,
+            header.group_count as usize * group_entry_size,
+        )?;
+        let groups = group_table.chunks(group_entry_size).map(|e| {
+            let size = BigEndian::read_u32(&e[4..8]);
+            GroupEntry {
+                data_offset: (BigEndian::read_u32(&e[0..4]) as u64) << 2,
+                data_size: size & 0x7fff_ffff,
+                is_compressed: !header.is_rvz || size & 0x8000_0000 != 0,
+                packed_size: if header.is_rvz { BigEndian::read_u32(&e[8..12]) } else { 0 },
+            }
+        }).collect();
+
+        Ok(WiaReader {
+            reader,
+            header,
+            raw_data,
+            groups,
+            position: 0,
+            current_group: None,
+        })
+    }
+
+    pub fn header(&self) -> &WiaHeader {
+        &self.header
+    }
+
+    // Decompresses a group, given its index and the offset on the disc
+    // where its data starts.
+    fn load_group(&mut self, index: usize, disc_offset: u64, size: usize) -> io::Result<()> {
+        if self.current_group.as_ref().map(|g| g.0) == Some(index) {
+            return Ok(());
+        }
+
+        let group = self.groups.get(index)
+            .ok_or_else(|| invalid_data("WIA group index out of range"))?;
+
+        let data = if group.data_size == 0 {
+            vec![0; size]
+        } else {
+            self.reader.seek(SeekFrom::Start(group.data_offset))?;
+            let mut raw = Vec::with_capacity(group.data_size as usize);
+            (&mut self.reader).take(group.data_size as u64).read_to_end(&mut raw)?;
+
+            let expected_size = if group.packed_size != 0 {
+                group.packed_size as usize
+            } else {
+                size
+            };
+            let data = if group.is_compressed {
+                decompress(&self.header, &raw, expected_size)?
+            } else {
+                raw
+            };
+
+            let mut data = if group.packed_size != 0 {
+                rvz_unpack(&data, disc_offset)?
+            } else {
+                data
+            };
+            data.resize(size, 0);
+            data
+        };
+
+        self.current_group = Some((index, data));
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Read for WiaReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let size = self.header.iso_file_size;
+        if self.position >= size || buf.is_empty() {
+            return Ok(0);
+        }
+        let pos = self.position;
+        let max_len = min(buf.len() as u64, size - pos) as usize;
+
+        // The first part of the disc header is stored in the WIA header itself
+        if pos < DISC_HEADER_SIZE as u64 {
+            let start = pos as usize;
+            let len = min(max_len, DISC_HEADER_SIZE - start);
+            buf[..len].copy_from_slice(&self.header.disc_header[start..start + len]);
+            self.position += len as u64;
+            return Ok(len);
+        }
+
+        let chunk_size = self.header.chunk_size as u64;
+        let entry = self.raw_data.iter()
+            .find(|e| e.data_offset <= pos && pos < e.data_offset + e.data_size)
+            .map(|e| (e.data_offset, e.data_size, e.group_index, e.group_count));
+
+        let len = match entry {
+            Some((entry_offset, entry_size, group_index, group_count)) => {
+                let group_number = (pos - entry_offset) / chunk_size;
+                if group_number >= group_count as u64 {
+                    return Err(invalid_data("WIA raw data is missing a group"));
+                }
+                let group_start = entry_offset + group_number * chunk_size;
+                let group_size = min(chunk_size, entry_offset + entry_size - group_start);
+
+                let index = (group_index as u64 + group_number) as usize;
+                self.load_group(index, group_start, group_size as usize)?;
+
+                let data = &self.current_group.as_ref().unwrap().1;
+                let start = (pos - group_start) as usize;
+                let len = min(max_len, data.len() - start);
+                buf[..len].copy_from_slice(&data[start..start + len]);
+                len
+            },
+            None => {
+                // Nothing is stored here, so it's just zeros
+                let next_entry = self.raw_data.iter()
+                    .map(|e| e.data_offset)
+                    .filter(|&o| o > pos)
+                    .min()
+                    .unwrap_or(size);
+                let len = min(max_len as u64, next_entry - pos) as usize;
+                for b in &mut buf[..len] { *b = 0 }
+                len
+            },
+        };
+
+        self.position += len as u64;
+        Ok(len)
+    }
+}
+
+impl<R: Read + Seek> Seek for WiaReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.position = seek_position(self.position, self.header.iso_file_size, pos)?;
+        Ok(self.position)
+    }
+}
+
+fn invalid_data(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+fn read_table(
+    mut reader: impl Read + Seek,
+    header: &WiaHeader,
+    offset: u64,
+    stored_size: u64,
+    size: usize,
+) -> io::Result<Vec<u8>> {
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut raw = Vec::with_capacity(stored_size as usize);
+    reader.take(stored_size).read_to_end(&mut raw)?;
+    let mut table = decompress(header, &raw, size)?;
+    if table.len() < size {
+        return Err(invalid_data("WIA table is too small"));
+    }
+    table.truncate(size);
+    Ok(table)
+}
+
+// Decompresses data that should be `size` bytes once decompressed.
+fn decompress(header: &WiaHeader, data: &[u8], size: usize) -> io::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(size);
+    match header.compression {
+        WiaCompression::None => out.extend_from_slice(data),
+        WiaCompression::Purge => out = purge_decompress(data, size)?,
+        WiaCompression::Bzip2 => {
+            BzDecoder::new(data).take(size as u64).read_to_end(&mut out)?;
+        },
+        WiaCompression::Lzma => {
+            // The compressor data is the usual 5 byte LZMA header,
+            // without the uncompressed size. The data ends with an end marker.
+            let options = Options {
+                unpacked_size: UnpackedSize::UseProvided(None),
+                ..Options::default()
+            };
+            let mut input = BufReader::new(header.compressor_data.as_slice().chain(data));
+            lzma_rs::lzma_decompress_with_options(&mut input, &mut out, &options)
+                .map_err(|e| invalid_data(format!("Invalid LZMA data: {:?}", e)))?;
+        },
+        WiaCompression::Lzma2 => {
+            lzma_rs::lzma2_decompress(&mut BufReader::new(data), &mut out)
+                .map_err(|e| invalid_data(format!("Invalid LZMA2 data: {:?}", e)))?;
+        },
+        WiaCompression::Zstd => {
+            StreamingDecoder::new(data)
+                .map_err(|e| invalid_data(format!("Invalid Zstandard data: {}", e)))?
+                .take(size as u64)
+                .read_to_end(&mut out)?;
+        },
+    }
+    Ok(out)
+}
+
+// Purge "compression" stores a list of the parts of the data that aren't
+// zero, each with an offset and size, followed by a SHA-1 hash.
+fn purge_decompress(data: &[u8], size: usize) -> io::Result<Vec<u8>> {
+    let mut out = vec![0; size];
+    let end = data.len().saturating_sub(SHA1_SIZE);
+    let mut pos = 0;
+    while pos + 8 <= end {
+        let offset = BigEndian::read_u32(&data[pos..]) as usize;
+        let len = BigEndian::read_u32(&data[pos + 4..]) as usize;
+        pos += 8;
+        if pos + len > end || offset + len > size {
+            return Err(invalid_data("Invalid purge segment"));
+        }
+        out[offset..offset + len].copy_from_slice(&data[pos..pos + len]);
+        pos += len;
+    }
+    Ok(out)
+}
+
+// RVZ packs data as a sequence of runs, each starting with a 32-bit size.
+// If the highest bit of the size is set, the run is junk data, which is
+// regenerated from a seed instead of being stored. Otherwise, the run is
+// stored as is. `disc_offset` is where the data starts on the disc,
+// which the junk data depends on.
+fn rvz_unpack(data: &[u8], mut disc_offset: u64) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+    let mut lfg = LaggedFibonacciGenerator::new();
+
+    while pos + 4 <= data.len() {
+        let size = BigEndian::read_u32(&data[pos..]);
+        pos += 4;
+        let len = (size & 0x7fff_ffff) as usize;
+
+        if size & 0x8000_0000 != 0 {
+            let seed_end = pos + LFG_SEED_SIZE * 4;
+            if seed_end > data.len() {
+                return Err(invalid_data("Invalid RVZ junk data"));
+            }
+            lfg.set_seed(&data[pos..seed_end]);
+            pos = seed_end;
+            lfg.forward_bytes((disc_offset % WII_BLOCK_SIZE) as usize);
+            lfg.read_bytes(len, &mut out);
+        } else {
+            if pos + len > data.len() {
+                return Err(invalid_data("Invalid RVZ packed data"));
+            }
+            out.extend_from_slice(&data[pos..pos + len]);
+            pos += len;
+        }
+        disc_offset += len as u64;
+    }
+    Ok(out)
+}
+
+const LFG_K: usize = 521;
+const LFG_J: usize = 32;
+const LFG_SEED_SIZE: usize = 17;
+
+// The random number generator used to make the junk data that fills unused
+// space on discs, from Dolphin's Source/Core/DiscIO/LaggedFibonacciGenerator.cpp
+struct LaggedFibonacciGenerator {
+    buffer: [u32; LFG_K],
+    position: usize,
+}
+
+impl LaggedFibonacciGenerator {
+    fn new() -> LaggedFibonacciGenerator {
+        LaggedFibonacciGenerator {
+            buffer: [0; LFG_K],
+            position: 0,
+        }
+    }
+
+    fn set_seed(&mut self, seed: &[u8]) {
+        self.position = 0;
+        for i in 0..LFG_SEED_SIZE {
+            self.buffer[i] = BigEndian::read_u32(&seed[i * 4..]);
+        }
+
+        for i in LFG_SEED_SIZE..LFG_K {
+            self.buffer[i] = (self.buffer[i - 17] << 23) ^
+                (self.buffer[i - 16] >> 9) ^
+                self.buffer[i - 1];
+        }
+
+        // The generated data is shifted by 18 rather than 16 bits in one spot
+        for x in self.buffer.iter_mut() {
+            *x = (*x & 0xff00_ffff) | ((*x >> 2) & 0x00ff_0000);
+        }
+
+        for _ in 0..4 {
+            self.forward();
+        }
+    }
+
+    fn forward(&mut self) {
+        for i in 0..LFG_J {
+            self.buffer[i] ^= self.buffer[i + LFG_K - LFG_J];
+        }
+        for i in LFG_J..LFG_K {
+            self.buffer[i] ^= self.buffer[i - LFG_J];
+        }
+    }
+
+    fn forward_bytes(&mut self, count: usize) {
+        self.position += count;
+        while self.position >= LFG_K * 4 {
+            self.forward();
+            self.position -= LFG_K * 4;
+        }
+    }
+
+    fn read_bytes(&mut self, mut count: usize, out: &mut Vec<u8>) {
+        while count > 0 {
+            let word = self.buffer[self.position / 4];
+            let len = min(count, 4 - self.position % 4);
+            let start = self.position % 4;
+            let mut bytes = [0; 4];
+            BigEndian::write_u32(&mut bytes, word);
+            out.extend_from_slice(&bytes[start..start + len]);
+            count -= len;
+            self.forward_bytes(len);
+        }
+    }
+}
@@ -0,0 +1,714 @@
+// The opcode tables here follow the "PowerPC Microprocessor Family:
+// The Programming Environments" manual and the Gekko/Broadway paired single
+// extensions, as documented by yagcd and Dolphin.
+
+use std::fmt;
+
+#[derive(Copy, Clone, Debug)]
+pub struct Instruction {
+    pub address: u32,
+    pub code: u32,
+}
+
+// The operands of an instruction, already formatted as text.
+// Most instructions have at most five operands (rlwinm), so a plain
+// `Vec` is fine here.
+type Operands = Vec<String>;
+
+fn r(n: u32) -> String {
+    format!("r{}", n)
+}
+
+fn f(n: u32) -> String {
+    format!("f{}", n)
+}
+
+fn cr(n: u32) -> String {
+    format!("cr{}", n)
+}
+
+fn hex(n: u32) -> String {
+    format!("{:#x}", n)
+}
+
+fn signed_hex(n: i32) -> String {
+    if n < 0 {
+        format!("-{:#x}", -(n as i64))
+    } else {
+        format!("{:#x}", n)
+    }
+}
+
+fn offset_reg(offset: i32, reg: u32) -> String {
+    format!("{}({})", signed_hex(offset), r(reg))
+}
+
+fn spr_name(spr: u32) -> Option<&'static str> {
+    Some(match spr {
+        1 => "xer",
+        8 => "lr",
+        9 => "ctr",
+        18 => "dsisr",
+        19 => "dar",
+        22 => "dec",
+        25 => "sdr1",
+        26 => "srr0",
+        27 => "srr1",
+        272 => "sprg0",
+        273 => "sprg1",
+        274 => "sprg2",
+        275 => "sprg3",
+        282 => "ear",
+        284 => "tbl",
+        285 => "tbu",
+        287 => "pvr",
+        528 => "ibat0u",
+        529 => "ibat0l",
+        530 => "ibat1u",
+        531 => "ibat1l",
+        532 => "ibat2u",
+        533 => "ibat2l",
+        534 => "ibat3u",
+        535 => "ibat3l",
+        536 => "dbat0u",
+        537 => "dbat0l",
+        538 => "dbat1u",
+        539 => "dbat1l",
+        540 => "dbat2u",
+        541 => "dbat2l",
+        542 => "dbat3u",
+        543 => "dbat3l",
+        912 => "gqr0",
+        913 => "gqr1",
+        914 => "gqr2",
+        915 => "gqr3",
+        916 => "gqr4",
+        917 => "gqr5",
+        918 => "gqr6",
+        919 => "gqr7",
+        920 => "hid2",
+        921 => "wpar",
+        922 => "dma_u",
+        923 => "dma_l",
+        936 => "ummcr0",
+        937 => "upmc1",
+        938 => "upmc2",
+        939 => "usia",
+        940 => "ummcr1",
+        941 => "upmc3",
+        942 => "upmc4",
+        952 => "mmcr0",
+        953 => "pmc1",
+        954 => "pmc2",
+        955 => "sia",
+        956 => "mmcr1",
+        957 => "pmc3",
+        958 => "pmc4",
+        1008 => "hid0",
+        1009 => "hid1",
+        1010 => "iabr",
+        1013 => "dabr",
+        1017 => "l2cr",
+        1019 => "ictc",
+        1020 => "thrm1",
+        1021 => "thrm2",
+        1022 => "thrm3",
+        _ => return None,
+    })
+}
+
+impl Instruction {
+    pub fn new(address: u32, code: u32) -> Instruction {
+        Instruction { address, code }
+    }
+
+    fn opcode(self) -> u32 {
+        self.code >> 26
+    }
+
+    fn rd(self) -> u32 {
+        (self.code >> 21) & 0x1f
+    }
+
+    fn ra(self) -> u32 {
+        (self.code >> 16) & 0x1f
+    }
+
+    fn rb(self) -> u32 {
+        (self.code >> 11) & 0x1f
+    }
+
+    fn rc(self) -> u32 {
+        (self.code >> 6) & 0x1f
+    }
+
+    fn crfd(self) -> u32 {
+        (self.code >> 23) & 0x7
+    }
+
+    fn crfs(self) -> u32 {
+        (self.code >> 18) & 0x7
+    }
+
+    fn simm(self) -> i32 {
+        (self.code & 0xffff) as i16 as i32
+    }
+
+    fn uimm(self) -> u32 {
+        self.code & 0xffff
+    }
+
+    fn record(self) -> bool {
+        self.code & 1 != 0
+    }
+
+    fn overflow(self) -> bool {
+        self.code & 0x400 != 0
+    }
+
+    fn link(self) -> bool {
+        self.code & 1 != 0
+    }
+
+    fn absolute(self) -> bool {
+        self.code & 2 != 0
+    }
+
+    fn xo10(self) -> u32 {
+        (self.code >> 1) & 0x3ff
+    }
+
+    fn xo9(self) -> u32 {
+        (self.code >> 1) & 0x1ff
+    }
+
+    fn xo5(self) -> u32 {
+        (self.code >> 1) & 0x1f
+    }
+
+    fn dot(self, name: &str) -> String {
+        if self.record() {
+            format!("{}.", name)
+        } else {
+            name.to_owned()
+        }
+    }
+
+    // Returns the address a branch instruction jumps to,
+    // or `None` if this isn't a direct branch.
+    pub fn branch_target(self) -> Option<u32> {
+        match self.opcode() {
+            18 => {
+                let li = ((self.code & 0x03ff_fffc) << 6) as i32 >> 6;
+                Some(self.relative_target(li))
+            },
+            16 => {
+                let bd = (self.code & 0xfffc) as i16 as i32;
+                Some(self.relative_target(bd))
+            },
+            _ => None,
+        }
+    }
+
+    fn relative_target(self, displacement: i32) -> u32 {
+        if self.absolute() {
+            displacement as u32
+        } else {
+            self.address.wrapping_add(displacement as u32)
+        }
+    }
+
+    // Returns `None` for invalid instructions
+    pub fn decode(self) -> Option<(String, Operands)> {
+        let (d, a, b, c) = (self.rd(), self.ra(), self.rb(), self.rc());
+        let simm = self.simm();
+        let uimm = self.uimm();
+
+        let (name, ops): (String, Operands) = match self.opcode() {
+            3 => ("twi".into(), vec![d.to_string(), r(a), signed_hex(simm)]),
+            4 => return self.decode_paired_single(),
+            7 => ("mulli".into(), vec![r(d), r(a), signed_hex(simm)]),
+            8 => ("subfic".into(), vec![r(d), r(a), signed_hex(simm)]),
+            10 => self.decode_compare_immediate("cmplwi", hex(uimm)),
+            11 => self.decode_compare_immediate("cmpwi", signed_hex(simm)),
+            12 => ("addic".into(), vec![r(d), r(a), signed_hex(simm)]),
+            13 => ("addic.".into(), vec![r(d), r(a), signed_hex(simm)]),
+            14 if a == 0 => ("li".into(), vec![r(d), signed_hex(simm)]),
+            14 => ("addi".into(), vec![r(d), r(a), signed_hex(simm)]),
+            15 if a == 0 => ("lis".into(), vec![r(d), hex(uimm)]),
+            15 => ("addis".into(), vec![r(d), r(a), hex(uimm)]),
+            16 => return Some(self.decode_bc()),
+            17 if self.code & 2 != 0 => ("sc".into(), vec![]),
+            18 => {
+                let mut name = String::from("b");
+                if self.link() { name.push('l') }
+                if self.absolute() { name.push('a') }
+                (name, vec![hex(self.branch_target().unwrap())])
+            },
+            19 => return self.decode_opcode_19(),
+            20 => (
+                self.dot("rlwimi"),
+                vec![r(a), r(d), b.to_string(), c.to_string(), self.me().to_string()],
+            ),
+            21 => self.decode_rlwinm(),
+            23 if self.mb() == 0 && self.me() == 31 =>
+                (self.dot("rotlw"), vec![r(a), r(d), r(b)]),
+            23 => (
+                self.dot("rlwnm"),
+                vec![r(a), r(d), r(b), c.to_string(), self.me().to_string()],
+            ),
+            24 if self.code == 0x6000_0000 => ("nop".into(), vec![]),
+            24 => ("ori".into(), vec![r(a), r(d), hex(uimm)]),
+            25 => ("oris".into(), vec![r(a), r(d), hex(uimm)]),
+            26 => ("xori".into(), vec![r(a), r(d), hex(uimm)]),
+            27 => ("xoris".into(), vec![r(a), r(d), hex(uimm)]),
+            28 => ("andi.".into(), vec![r(a), r(d), hex(uimm)]),
+            29 => ("andis.".into(), vec![r(a), r(d), hex(uimm)]),
+            31 => return self.decode_opcode_31(),
+            32..=55 => {
+                let name = [
+                    "lwz", "lwzu", "lbz", "lbzu", "stw", "stwu", "stb", "stbu",
+                    "lhz", "lhzu", "lha", "lhau", "sth", "sthu", "lmw", "stmw",
+                    "lfs", "lfsu", "lfd", "lfdu", "stfs", "stfsu", "stfd", "stfdu",
+                ][self.opcode() as usize - 32];
+                let reg = if self.opcode() >= 48 { f(d) } else { r(d) };
+                (name.into(), vec![reg, offset_reg(simm, a)])
+            },
+            56 | 57 | 60 | 61 => {
+                let name = match self.opcode() {
+                    56 => "psq_l",
+                    57 => "psq_lu",
+                    60 => "psq_st",
+                    _ => "psq_stu",
+                };
+                let offset = ((self.code & 0xfff) << 20) as i32 >> 20;
+                let w = (self.code >> 15) & 1;
+                let i = (self.code >> 12) & 7;
+                (
+                    name.into(),
+                    vec![f(d), offset_reg(offset, a), w.to_string(), i.to_string()],
+                )
+            },
+            59 => return self.decode_opcode_59(),
+            63 => return self.decode_opcode_63(),
+            _ => return None,
+        };
+        Some((name, ops))
+    }
+
+    fn mb(self) -> u32 {
+        (self.code >> 6) & 0x1f
+    }
+
+    fn me(self) -> u32 {
+        (self.code >> 1) & 0x1f
+    }
+
+    fn decode_compare_immediate(self, name: &str, imm: String) -> (String, Operands) {
+        let mut ops = vec![r(self.ra()), imm];
+        if self.crfd() != 0 {
+            ops.insert(0, cr(self.crfd()));
+        }
+        (name.into(), ops)
+    }
+
+    fn decode_rlwinm(self) -> (String, Operands) {
+        let (a, s, sh) = (self.ra(), self.rd(), self.rb());
+        let (mb, me) = (self.mb(), self.me());
+        let (name, n) = if mb == 0 && me == 31 {
+            ("rotlwi", sh)
+        } else if mb == 0 && sh == 31 - me {
+            ("slwi", sh)
+        } else if me == 31 && sh != 0 && sh == 32 - mb {
+            ("srwi", mb)
+        } else if sh == 0 && me == 31 {
+            ("clrlwi", mb)
+        } else if sh == 0 && mb == 0 {
+            ("clrrwi", 31 - me)
+        } else {
+            return (
+                self.dot("rlwinm"),
+                vec![r(a), r(s), sh.to_string(), mb.to_string(), me.to_string()],
+            );
+        };
+        (self.dot(name), vec![r(a), r(s), n.to_string()])
+    }
+
+    // Shared by bc, bclr, and bcctr. `suffix` is "" for bc, "lr" or "ctr".
+    // Returns the mnemonic, and the condition register operand if one is needed.
+    fn branch_conditional_name(self, suffix: &str) -> (String, Option<String>) {
+        let bo = self.rd();
+        let bi = self.ra();
+
+        let ignores_condition = bo & 0x10 != 0;
+        let branch_if_true = bo & 0x08 != 0;
+        let decrements_ctr = bo & 0x04 == 0;
+        let ctr_cond = if bo & 0x02 != 0 { "z" } else { "nz" };
+        let cr_cond = if branch_if_true { "t" } else { "f" };
+
+        // The lowest bit of BO is just a branch prediction hint, so it's ignored
+        let (mut name, cr_field) = match (decrements_ctr, ignores_condition) {
+            (false, true) => (format!("b{}", suffix), None),
+            (true, true) => (format!("bd{}{}", ctr_cond, suffix), None),
+            (true, false) =>
+                (format!("bd{}{}{}", ctr_cond, cr_cond, suffix), Some(bi.to_string())),
+            (false, false) => {
+                let cond = match (bi % 4, branch_if_true) {
+                    (0, true) => "lt",
+                    (1, true) => "gt",
+                    (2, true) => "eq",
+                    (3, true) => "so",
+                    (0, false) => "ge",
+                    (1, false) => "le",
+                    (2, false) => "ne",
+                    _ => "ns",
+                };
+                let cr_field = if bi / 4 != 0 { Some(cr(bi / 4)) } else { None };
+                (format!("b{}{}", cond, suffix), cr_field)
+            },
+        };
+        if self.link() { name.push('l') }
+        (name, cr_field)
+    }
+
+    fn decode_bc(self) -> (String, Operands) {
+        let (mut name, cr_field) = self.branch_conditional_name("");
+        if self.absolute() { name.push('a') }
+        let mut ops: Operands = cr_field.into_iter().collect();
+        ops.push(hex(self.branch_target().unwrap()));
+        (name, ops)
+    }
+
+    fn decode_opcode_19(self) -> Option<(String, Operands)> {
+        let (d, a, b) = (self.rd(), self.ra(), self.rb());
+        let cr_op = |name: &str| -> (String, Operands) {
+            (name.into(), vec![d.to_string(), a.to_string(), b.to_string()])
+        };
+        Some(match self.xo10() {
+            0 => ("mcrf".into(), vec![cr(self.crfd()), cr(self.crfs())]),
+            16 => {
+                let (name, cr_field) = self.branch_conditional_name("lr");
+                (name, cr_field.into_iter().collect())
+            },
+            33 => cr_op("crnor"),
+            50 => ("rfi".into(), vec![]),
+            129 => cr_op("crandc"),
+            150 => ("isync".into(), vec![]),
+            193 if d == a && a == b => ("crclr".into(), vec![d.to_string()]),
+            193 => cr_op("crxor"),
+            225 => cr_op("crnand"),
+            257 => cr_op("crand"),
+            289 if d == a && a == b => ("crset".into(), vec![d.to_string()]),
+            289 => cr_op("creqv"),
+            417 => cr_op("crorc"),
+            449 => cr_op("cror"),
+            528 => {
+                let (name, cr_field) = self.branch_conditional_name("ctr");
+                (name, cr_field.into_iter().collect())
+            },
+            _ => return None,
+        })
+    }
+
+    fn decode_opcode_31(self) -> Option<(String, Operands)> {
+        let (d, a, b) = (self.rd(), self.ra(), self.rb());
+
+        // XO-form arithmetic, which has an overflow-enable bit
+        // above the 9-bit extended opcode
+        let xo_name = match self.xo9() {
+            8 => Some("subfc"),
+            10 => Some("addc"),
+            11 => Some("mulhwu"),
+            40 => Some("subf"),
+            75 => Some("mulhw"),
+            104 => Some("neg"),
+            136 => Some("subfe"),
+            138 => Some("adde"),
+            200 => Some("subfze"),
+            202 => Some("addze"),
+            232 => Some("subfme"),
+            234 => Some("addme"),
+            235 => Some("mullw"),
+            266 => Some("add"),
+            459 => Some("divwu"),
+            491 => Some("divw"),
+            _ => None,
+        };
+        if let Some(name) = xo_name {
+            let mut name = name.to_owned();
+            if self.overflow() { name.push('o') }
+            if self.record() { name.push('.') }
+            let ops = match self.xo9() {
+                104 | 200 | 202 | 232 | 234 => vec![r(d), r(a)],
+                _ => vec![r(d), r(a), r(b)],
+            };
+            return Some((name, ops));
+        }
+
+        let indexed = |name: &str| -> (String, Operands) {
+            (name.into(), vec![r(d), r(a), r(b)])
+        };
+        let indexed_float = |name: &str| -> (String, Operands) {
+            (name.into(), vec![f(d), r(a), r(b)])
+        };
+        // Logical ops put rA first
+        let logical = |name: &str| -> (String, Operands) {
+            (self.dot(name), vec![r(a), r(d), r(b)])
+        };
+        let cache = |name: &str| -> (String, Operands) {
+            (name.into(), vec![r(a), r(b)])
+        };
+
+        Some(match self.xo10() {
+            0 | 32 => {
+                let name = if self.xo10() == 0 { "cmpw" } else { "cmplw" };
+                let mut ops = vec![r(a), r(b)];
+                if self.crfd() != 0 {
+                    ops.insert(0, cr(self.crfd()));
+                }
+                (name.into(), ops)
+            },
+            4 if d == 31 && a == 0 && b == 0 => ("trap".into(), vec![]),
+            4 => ("tw".into(), vec![d.to_string(), r(a), r(b)]),
+            19 => ("mfcr".into(), vec![r(d)]),
+            20 => indexed("lwarx"),
+            23 => indexed("lwzx"),
+            24 => logical("slw"),
+            26 => (self.dot("cntlzw"), vec![r(a), r(d)]),
+            28 => logical("and"),
+            54 => cache("dcbst"),
+            55 => indexed("lwzux"),
+            60 => logical("andc"),
+            83 => ("mfmsr".into(), vec![r(d)]),
+            86 => cache("dcbf"),
+            87 => indexed("lbzx"),
+            119 => indexed("lbzux"),
+            124 if d == b => (self.dot("not"), vec![r(a), r(d)]),
+            124 => logical("nor"),
+            144 => {
+                let crm = (self.code >> 12) & 0xff;
+                if crm == 0xff {
+                    ("mtcr".into(), vec![r(d)])
+                } else {
+                    ("mtcrf".into(), vec![hex(crm), r(d)])
+                }
+            },
+            146 => ("mtmsr".into(), vec![r(d)]),
+            150 => indexed("stwcx."),
+            151 => indexed("stwx"),
+            183 => indexed("stwux"),
+            210 => ("mtsr".into(), vec![((self.code >> 16) & 0xf).to_string(), r(d)]),
+            215 => indexed("stbx"),
+            242 => ("mtsrin".into(), vec![r(d), r(b)]),
+            246 => cache("dcbtst"),
+            247 => indexed("stbux"),
+            278 => cache("dcbt"),
+            279 => indexed("lhzx"),
+            284 => logical("eqv"),
+            306 => ("tlbie".into(), vec![r(b)]),
+            310 => indexed("eciwx"),
+            311 => indexed("lhzux"),
+            316 => logical("xor"),
+            339 | 467 => {
+                let spr = ((self.code >> 16) & 0x1f) | (((self.code >> 11) & 0x1f) << 5);
+                let is_mf = self.xo10() == 339;
+                match (spr_name(spr), is_mf) {
+                    (Some(n @ "xer"), true) | (Some(n @ "lr"), true) | (Some(n @ "ctr"), true) =>
+                        (format!("mf{}", n), vec![r(d)]),
+                    (Some(n @ "xer"), false) | (Some(n @ "lr"), false) | (Some(n @ "ctr"), false) =>
+                        (format!("mt{}", n), vec![r(d)]),
+                    (name, true) =>
+                        ("mfspr".into(), vec![r(d), name.map(String::from).unwrap_or_else(|| spr.to_string())]),
+                    (name, false) =>
+                        ("mtspr".into(), vec![name.map(String::from).unwrap_or_else(|| spr.to_string()), r(d)]),
+                }
+            },
+            343 => indexed("lhax"),
+            370 => ("tlbia".into(), vec![]),
+            371 => {
+                let tbr = ((self.code >> 16) & 0x1f) | (((self.code >> 11) & 0x1f) << 5);
+                match tbr {
+                    268 => ("mftb".into(), vec![r(d)]),
+                    269 => ("mftbu".into(), vec![r(d)]),
+                    _ => ("mftb".into(), vec![r(d), tbr.to_string()]),
+                }
+            },
+            375 => indexed("lhaux"),
+            407 => indexed("sthx"),
+            412 => logical("orc"),
+            438 => indexed("ecowx"),
+            439 => indexed("sthux"),
+            444 if d == b => (self.dot("mr"), vec![r(a), r(d)]),
+            444 => logical("or"),
+            470 => cache("dcbi"),
+            476 => logical("nand"),
+            512 => ("mcrxr".into(), vec![cr(self.crfd())]),
+            533 => indexed("lswx"),
+            534 => indexed("lwbrx"),
+            535 => indexed_float("lfsx"),
+            536 => logical("srw"),
+            566 => ("tlbsync".into(), vec![]),
+            567 => indexed_float("lfsux"),
+            595 => ("mfsr".into(), vec![r(d), ((self.code >> 16) & 0xf).to_string()]),
+            597 => ("lswi".into(), vec![r(d), r(a), b.to_string()]),
+            598 => ("sync".into(), vec![]),
+            599 => indexed_float("lfdx"),
+            631 => indexed_float("lfdux"),
+            659 => ("mfsrin".into(), vec![r(d), r(b)]),
+            661 => indexed("stswx"),
+            662 => indexed("stwbrx"),
+            663 => indexed_float("stfsx"),
+            695 => indexed_float("stfsux"),
+            725 => ("stswi".into(), vec![r(d), r(a), b.to_string()]),
+            727 => indexed_float("stfdx"),
+            759 => indexed_float("stfdux"),
+            790 => indexed("lhbrx"),
+            792 => logical("sraw"),
+            824 => (self.dot("srawi"), vec![r(a), r(d), b.to_string()]),
+            854 => ("eieio".into(), vec![]),
+            918 => indexed("sthbrx"),
+            922 => (self.dot("extsh"), vec![r(a), r(d)]),
+            954 => (self.dot("extsb"), vec![r(a), r(d)]),
+            982 => cache("icbi"),
+            983 => indexed_float("stfiwx"),
+            1014 => cache("dcbz"),
+            _ => return None,
+        })
+    }
+
+    // Single precision floating point arithmetic
+    fn decode_opcode_59(self) -> Option<(String, Operands)> {
+        let (d, a, b, c) = (self.rd(), self.ra(), self.rb(), self.rc());
+        Some(match self.xo5() {
+            18 => (self.dot("fdivs"), vec![f(d), f(a), f(b)]),
+            20 => (self.dot("fsubs"), vec![f(d), f(a), f(b)]),
+            21 => (self.dot("fadds"), vec![f(d), f(a), f(b)]),
+            24 => (self.dot("fres"), vec![f(d), f(b)]),
+            25 => (self.dot("fmuls"), vec![f(d), f(a), f(c)]),
+            28 => (self.dot("fmsubs"), vec![f(d), f(a), f(c), f(b)]),
+            29 => (self.dot("fmadds"), vec![f(d), f(a), f(c), f(b)]),
+            30 => (self.dot("fnmsubs"), vec![f(d), f(a), f(c), f(b)]),
+            31 => (self.dot("fnmadds"), vec![f(d), f(a), f(c), f(b)]),
+            _ => return None,
+        })
+    }
+
+    // Double precision floating point arithmetic and FPSCR access
+    fn decode_opcode_63(self) -> Option<(String, Operands)> {
+        let (d, a, b, c) = (self.rd(), self.ra(), self.rb(), self.rc());
+        let a_form = match self.xo5() {
+            18 => Some((self.dot("fdiv"), vec![f(d), f(a), f(b)])),
+            20 => Some((self.dot("fsub"), vec![f(d), f(a), f(b)])),
+            21 => Some((self.dot("fadd"), vec![f(d), f(a), f(b)])),
+            23 => Some((self.dot("fsel"), vec![f(d), f(a), f(c), f(b)])),
+            25 => Some((self.dot("fmul"), vec![f(d), f(a), f(c)])),
+            26 => Some((self.dot("frsqrte"), vec![f(d), f(b)])),
+            28 => Some((self.dot("fmsub"), vec![f(d), f(a), f(c), f(b)])),
+            29 => Some((self.dot("fmadd"), vec![f(d), f(a), f(c), f(b)])),
+            30 => Some((self.dot("fnmsub"), vec![f(d), f(a), f(c), f(b)])),
+            31 => Some((self.dot("fnmadd"), vec![f(d), f(a), f(c), f(b)])),
+            _ => None,
+        };
+        if a_form.is_some() {
+            return a_form;
+        }
+
+        Some(match self.xo10() {
+            0 => ("fcmpu".into(), vec![cr(self.crfd()), f(a), f(b)]),
+            12 => (self.dot("frsp"), vec![f(d), f(b)]),
+            14 => (self.dot("fctiw"), vec![f(d), f(b)]),
+            15 => (self.dot("fctiwz"), vec![f(d), f(b)]),
+            32 => ("fcmpo".into(), vec![cr(self.crfd()), f(a), f(b)]),
+            38 => (self.dot("mtfsb1"), vec![d.to_string()]),
+            40 => (self.dot("fneg"), vec![f(d), f(b)]),
+            64 => ("mcrfs".into(), vec![cr(self.crfd()), cr(self.crfs())]),
+            70 => (self.dot("mtfsb0"), vec![d.to_string()]),
+            72 => (self.dot("fmr"), vec![f(d), f(b)]),
+            134 => (
+                self.dot("mtfsfi"),
+                vec![cr(self.crfd()), ((self.code >> 12) & 0xf).to_string()],
+            ),
+            136 => (self.dot("fnabs"), vec![f(d), f(b)]),
+            264 => (self.dot("fabs"), vec![f(d), f(b)]),
+            583 => (self.dot("mffs"), vec![f(d)]),
+            711 => (
+                self.dot("mtfsf"),
+                vec![hex((self.code >> 17) & 0xff), f(b)],
+            ),
+            _ => return None,
+        })
+    }
+
+    // Gekko's paired single instructions, which replace the
+    // opcode 4 instructions of other PowerPC processors
+    fn decode_paired_single(self) -> Option<(String, Operands)> {
+        let (d, a, b, c) = (self.rd(), self.ra(), self.rb(), self.rc());
+
+        let a_form = match self.xo5() {
+            10 => Some((self.dot("ps_sum0"), vec![f(d), f(a), f(c), f(b)])),
+            11 => Some((self.dot("ps_sum1"), vec![f(d), f(a), f(c), f(b)])),
+            12 => Some((self.dot("ps_muls0"), vec![f(d), f(a), f(c)])),
+            13 => Some((self.dot("ps_muls1"), vec![f(d), f(a), f(c)])),
+            14 => Some((self.dot("ps_madds0"), vec![f(d), f(a), f(c), f(b)])),
+            15 => Some((self.dot("ps_madds1"), vec![f(d), f(a), f(c), f(b)])),
+            18 => Some((self.dot("ps_div"), vec![f(d), f(a), f(b)])),
+            20 => Some((self.dot("ps_sub"), vec![f(d), f(a), f(b)])),
+            21 => Some((self.dot("ps_add"), vec![f(d), f(a), f(b)])),
+            23 => Some((self.dot("ps_sel"), vec![f(d), f(a), f(c), f(b)])),
+            24 => Some((self.dot("ps_res"), vec![f(d), f(b)])),
+            25 => Some((self.dot("ps_mul"), vec![f(d), f(a), f(c)])),
+            26 => Some((self.dot("ps_rsqrte"), vec![f(d), f(b)])),
+            28 => Some((self.dot("ps_msub"), vec![f(d), f(a), f(c), f(b)])),
+            29 => Some((self.dot("ps_madd"), vec![f(d), f(a), f(c), f(b)])),
+            30 => Some((self.dot("ps_nmsub"), vec![f(d), f(a), f(c), f(b)])),
+            31 => Some((self.dot("ps_nmadd"), vec![f(d), f(a), f(c), f(b)])),
+            _ => None,
+        };
+        if a_form.is_some() {
+            return a_form;
+        }
+
+        let w = (self.code >> 10) & 1;
+        let i = (self.code >> 7) & 7;
+        let indexed = match (self.code >> 1) & 0x3f {
+            6 => Some("psq_lx"),
+            7 => Some("psq_stx"),
+            38 => Some("psq_lux"),
+            39 => Some("psq_stux"),
+            _ => None,
+        };
+        if let Some(name) = indexed {
+            return Some((
+                name.into(),
+                vec![f(d), r(a), r(b), w.to_string(), i.to_string()],
+            ));
+        }
+
+        Some(match self.xo10() {
+            0 => ("ps_cmpu0".into(), vec![cr(self.crfd()), f(a), f(b)]),
+            32 => ("ps_cmpo0".into(), vec![cr(self.crfd()), f(a), f(b)]),
+            40 => (self.dot("ps_neg"), vec![f(d), f(b)]),
+            64 => ("ps_cmpu1".into(), vec![cr(self.crfd()), f(a), f(b)]),
+            72 => (self.dot("ps_mr"), vec![f(d), f(b)]),
+            96 => ("ps_cmpo1".into(), vec![cr(self.crfd()), f(a), f(b)]),
+            136 => (self.dot("ps_nabs"), vec![f(d), f(b)]),
+            264 => (self.dot("ps_abs"), vec![f(d), f(b)]),
+            528 => (self.dot("ps_merge00"), vec![f(d), f(a), f(b)]),
+            560 => (self.dot("ps_merge01"), vec![f(d), f(a), f(b)]),
+            592 => (self.dot("ps_merge10"), vec![f(d), f(a), f(b)]),
+            624 => (self.dot("ps_merge11"), vec![f(d), f(a), f(b)]),
+            1014 => ("dcbz_l".into(), vec![r(a), r(b)]),
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self.decode() {
+            Some((name, ops)) if ops.is_empty() => write!(fmt, "{}", name),
+            Some((name, ops)) => write!(fmt, "{:<9} {}", name, ops.join(", ")),
+            None => write!(fmt, "{:<9} {:#010x}", ".word", self.code),
+        }
+    }
+}
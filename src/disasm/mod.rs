@@ -0,0 +1,84 @@
+pub mod instruction;
+
+use std::cmp::{max, min};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use byteorder::{BigEndian, ByteOrder};
+
+use ::parse_as_u64;
+use sections::dol::segment::Segment;
+
+pub use self::instruction::Instruction;
+
+// An inclusive start and exclusive end of a range of virtual addresses
+#[derive(Copy, Clone, Debug)]
+pub struct AddressRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl AddressRange {
+    // Parses ranges in the form `start..end`, like `0x80003100..0x80003200`
+    pub fn parse(text: &str) -> Option<AddressRange> {
+        let mut parts = text.splitn(2, "..");
+        let start = parse_as_u64(parts.next()?.trim()).ok()?;
+        let end = parse_as_u64(parts.next()?.trim()).ok()?;
+        if start < end {
+            Some(AddressRange { start, end })
+        } else {
+            None
+        }
+    }
+
+    pub fn overlaps(&self, seg: &Segment) -> bool {
+        self.start < seg.loading_address + seg.size as u64 &&
+            seg.loading_address < self.end
+    }
+}
+
+// Disassembles the bytes in `code`, which would be loaded at `address`.
+pub fn disassemble(code: &[u8], address: u32, mut output: impl Write) -> io::Result<()> {
+    for (i, word) in code.chunks(4).enumerate() {
+        let addr = address.wrapping_add(i as u32 * 4);
+        if word.len() < 4 {
+            // A trailing partial word can't be an instruction
+            writeln!(output, "{:08x}  {:<8}  .byte", addr, word.iter()
+                .map(|b| format!("{:02x}", b)).collect::<String>())?;
+            break;
+        }
+        let code = BigEndian::read_u32(word);
+        writeln!(output, "{:08x}  {:08x}  {}", addr, code, Instruction::new(addr, code))?;
+    }
+    Ok(())
+}
+
+// Disassembles a DOL segment, or just the part of it in `range` if one is given.
+pub fn disassemble_segment<R, W>(
+    mut iso: R,
+    segment: &Segment,
+    range: Option<AddressRange>,
+    mut output: W,
+) -> io::Result<()>
+where
+    R: Read + Seek,
+    W: Write,
+{
+    let seg_start = segment.loading_address;
+    let seg_end = seg_start + segment.size as u64;
+    let (start, end) = match range {
+        Some(r) => (max(r.start, seg_start), min(r.end, seg_end)),
+        None => (seg_start, seg_end),
+    };
+    if start >= end {
+        return Ok(());
+    }
+    // Keep instructions word aligned, relative to the start of the segment
+    let start = start - (start - seg_start) % 4;
+
+    let mut code = Vec::with_capacity((end - start) as usize);
+    iso.seek(SeekFrom::Start(segment.offset + (start - seg_start)))?;
+    iso.take(end - start).read_to_end(&mut code)?;
+
+    writeln!(output, "{}:", segment.to_string())?;
+    disassemble(&code, start as u32, &mut output)
+}
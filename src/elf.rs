@@ -0,0 +1,391 @@
+// Just enough of the ELF format to convert between 32-bit big endian
+// PowerPC ELF files and DOLs.
+// https://refspecs.linuxfoundation.org/elf/elf.pdf
+
+use std::cmp::{max, min};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use ::align;
+use sections::dol::Dol;
+use sections::dol::segment::SegmentType;
+use symbols::SymbolMap;
+
+pub const ELF_MAGIC: &[u8; 4] = b"\x7fELF";
+
+const ELF_CLASS_32: u8 = 1;
+const ELF_DATA_BIG_ENDIAN: u8 = 2;
+const ELF_VERSION: u8 = 1;
+const ET_EXEC: u16 = 2;
+pub const EM_PPC: u16 = 20;
+
+const ELF_HEADER_SIZE: u16 = 52;
+const PROGRAM_HEADER_SIZE: u16 = 32;
+const SECTION_HEADER_SIZE: u16 = 40;
+const SYMBOL_SIZE: u32 = 16;
+
+// Section types
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_NOBITS: u32 = 8;
+
+// Section flags
+const SHF_WRITE: u32 = 1;
+const SHF_ALLOC: u32 = 2;
+const SHF_EXECINSTR: u32 = 4;
+
+// Symbol bindings, types, and special section indices
+const STB_GLOBAL: u8 = 1;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+const SHN_ABS: u16 = 0xFFF1;
+
+// Where segment data starts in the files `dol_to_elf` writes is aligned to this
+const DATA_ALIGNMENT: u64 = 32;
+
+pub const PT_LOAD: u32 = 1;
+
+// Program header flags
+pub const PF_X: u32 = 1;
+pub const PF_W: u32 = 2;
+pub const PF_R: u32 = 4;
+
+#[derive(Copy, Clone, Debug)]
+pub struct ProgramHeader {
+    pub p_type: u32,
+    pub offset: u32,
+    pub virtual_address: u32,
+    pub physical_address: u32,
+    pub file_size: u32,
+    pub memory_size: u32,
+    pub flags: u32,
+    pub align: u32,
+}
+
+#[derive(Debug)]
+pub struct Elf {
+    pub entry_point: u32,
+    pub program_headers: Vec<ProgramHeader>,
+}
+
+impl Elf {
+    pub fn new(mut file: impl Read + Seek) -> io::Result<Elf> {
+        file.seek(SeekFrom::Start(0))?;
+        let mut ident = [0; 16];
+        file.read_exact(&mut ident)?;
+        if &ident[..4] != ELF_MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Not an ELF file"));
+        }
+        if ident[4] != ELF_CLASS_32 || ident[5] != ELF_DATA_BIG_ENDIAN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Only 32-bit big endian ELF files are supported",
+            ));
+        }
+
+        let _file_type = file.read_u16::<BigEndian>()?;
+        if file.read_u16::<BigEndian>()? != EM_PPC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a PowerPC ELF file"));
+        }
+        let _version = file.read_u32::<BigEndian>()?;
+        let entry_point = file.read_u32::<BigEndian>()?;
+        let ph_offset = file.read_u32::<BigEndian>()?;
+        let _sh_offset = file.read_u32::<BigEndian>()?;
+        let _flags = file.read_u32::<BigEndian>()?;
+        let _header_size = file.read_u16::<BigEndian>()?;
+        let ph_entry_size = file.read_u16::<BigEndian>()?;
+        let ph_count = file.read_u16::<BigEndian>()?;
+
+        let mut program_headers = Vec::with_capacity(ph_count as usize);
+        for i in 0..ph_count as u64 {
+            file.seek(SeekFrom::Start(ph_offset as u64 + i * ph_entry_size as u64))?;
+            program_headers.push(ProgramHeader {
+                p_type: file.read_u32::<BigEndian>()?,
+                offset: file.read_u32::<BigEndian>()?,
+                virtual_address: file.read_u32::<BigEndian>()?,
+                physical_address: file.read_u32::<BigEndian>()?,
+                file_size: file.read_u32::<BigEndian>()?,
+                memory_size: file.read_u32::<BigEndian>()?,
+                flags: file.read_u32::<BigEndian>()?,
+                align: file.read_u32::<BigEndian>()?,
+            });
+        }
+
+        Ok(Elf {
+            entry_point,
+            program_headers,
+        })
+    }
+
+    pub fn is_elf(mut file: impl Read + Seek) -> io::Result<bool> {
+        file.seek(SeekFrom::Start(0))?;
+        let mut magic = [0; 4];
+        let bytes_read = file.read(&mut magic)?;
+        file.seek(SeekFrom::Start(0))?;
+        Ok(bytes_read == magic.len() && &magic == ELF_MAGIC)
+    }
+}
+
+// Converts an ELF file to a DOL. Every loadable segment becomes a text
+// segment if it's executable, or a data segment otherwise. The parts of
+// segments that take up more space in memory than in the file make up the BSS.
+pub fn elf_to_dol(mut file: impl Read + Seek) -> io::Result<Dol> {
+    let elf = Elf::new(&mut file)?;
+    let mut dol = Dol::new(elf.entry_point as u64);
+
+    let mut bss: Option<(u64, u64)> = None;
+    for ph in elf.program_headers.iter().filter(|ph| ph.p_type == PT_LOAD) {
+        if ph.file_size > 0 {
+            let mut data = Vec::with_capacity(ph.file_size as usize);
+            file.seek(SeekFrom::Start(ph.offset as u64))?;
+            (&mut file).take(ph.file_size as u64).read_to_end(&mut data)?;
+            if data.len() != ph.file_size as usize {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "An ELF segment is cut off",
+                ));
+            }
+
+            let seg_type = if ph.flags & PF_X != 0 {
+                SegmentType::Text
+            } else {
+                SegmentType::Data
+            };
+            dol.add_segment(seg_type, ph.virtual_address as u64, data)?;
+        }
+
+        if ph.memory_size > ph.file_size {
+            let start = ph.virtual_address as u64 + ph.file_size as u64;
+            let end = ph.virtual_address as u64 + ph.memory_size as u64;
+            bss = Some(match bss {
+                Some((s, e)) => (min(s, start), max(e, end)),
+                None => (start, end),
+            });
+        }
+    }
+
+    if let Some((start, end)) = bss {
+        dol.bss_address = start;
+        dol.bss_size = (end - start) as usize;
+    }
+
+    Ok(dol)
+}
+
+struct SectionHeader {
+    name: u32,
+    sh_type: u32,
+    flags: u32,
+    address: u32,
+    offset: u32,
+    size: u32,
+    link: u32,
+    info: u32,
+    align: u32,
+    entry_size: u32,
+}
+
+// Adds a string to a string table, returning its offset in the table
+fn add_string(table: &mut Vec<u8>, s: &str) -> u32 {
+    let offset = table.len() as u32;
+    table.extend_from_slice(s.as_bytes());
+    table.push(0);
+    offset
+}
+
+// Converts a DOL to an ELF file with a section for each segment, named like
+// `.text0`, plus a `.bss` section. Each section also gets a loadable segment,
+// so the ELF can be converted back with `elf_to_dol`. If a symbol map is given,
+// the symbols are added to a `.symtab` section.
+pub fn dol_to_elf(dol: &Dol, symbols: Option<&SymbolMap>, mut writer: impl Write) -> io::Result<()> {
+    let segments: Vec<_> = dol.iter_segments().collect();
+    let has_bss = dol.bss_size > 0;
+    let program_header_count = segments.len() + has_bss as usize;
+
+    let mut section_names = vec![0];
+    let mut sections = vec![SectionHeader {
+        name: 0, sh_type: 0, flags: 0, address: 0, offset: 0,
+        size: 0, link: 0, info: 0, align: 0, entry_size: 0,
+    }];
+
+    // The segment data comes right after the ELF and program headers
+    let mut offset = align(
+        ELF_HEADER_SIZE as u64 + PROGRAM_HEADER_SIZE as u64 * program_header_count as u64,
+        DATA_ALIGNMENT,
+    );
+    let data_start = offset;
+    for s in &segments {
+        let flags = match s.seg_type {
+            SegmentType::Text => SHF_ALLOC | SHF_EXECINSTR,
+            SegmentType::Data => SHF_ALLOC | SHF_WRITE,
+        };
+        sections.push(SectionHeader {
+            name: add_string(&mut section_names, &s.name()),
+            sh_type: SHT_PROGBITS,
+            flags,
+            address: s.loading_address as u32,
+            offset: offset as u32,
+            size: s.size() as u32,
+            link: 0,
+            info: 0,
+            align: DATA_ALIGNMENT as u32,
+            entry_size: 0,
+        });
+        offset = align(offset + s.size() as u64, DATA_ALIGNMENT);
+    }
+    if has_bss {
+        sections.push(SectionHeader {
+            name: add_string(&mut section_names, ".bss"),
+            sh_type: SHT_NOBITS,
+            flags: SHF_ALLOC | SHF_WRITE,
+            address: dol.bss_address as u32,
+            offset: offset as u32,
+            size: dol.bss_size as u32,
+            link: 0,
+            info: 0,
+            align: DATA_ALIGNMENT as u32,
+            entry_size: 0,
+        });
+    }
+
+    let mut symbol_table = Vec::new();
+    let mut string_table = Vec::new();
+    if let Some(symbols) = symbols {
+        // The first symbol is always an empty one
+        symbol_table.resize(SYMBOL_SIZE as usize, 0);
+        string_table.push(0);
+        // Segments come first, since the BSS range can overlap the small data ones
+        let bss = dol.bss_address..dol.bss_address + dol.bss_size as u64;
+        let bss_index = segments.len() as u16 + 1;
+        for sym in symbols.iter() {
+            let section_index = segments.iter()
+                .position(|s| s.contains_addr(sym.address))
+                .map(|i| i as u16 + 1)
+                .or_else(|| if has_bss && bss.contains(&sym.address) {
+                    Some(bss_index)
+                } else {
+                    None
+                })
+                .unwrap_or(SHN_ABS);
+            let sym_type = if sym.is_function() { STT_FUNC } else { STT_OBJECT };
+            symbol_table.write_u32::<BigEndian>(add_string(&mut string_table, &sym.name))?;
+            symbol_table.write_u32::<BigEndian>(sym.address as u32)?;
+            symbol_table.write_u32::<BigEndian>(sym.size)?;
+            symbol_table.write_u8((STB_GLOBAL << 4) | sym_type)?;
+            symbol_table.write_u8(0)?;
+            symbol_table.write_u16::<BigEndian>(section_index)?;
+        }
+
+        let string_table_index = sections.len() as u32 + 1;
+        sections.push(SectionHeader {
+            name: add_string(&mut section_names, ".symtab"),
+            sh_type: SHT_SYMTAB,
+            flags: 0,
+            address: 0,
+            offset: offset as u32,
+            size: symbol_table.len() as u32,
+            link: string_table_index,
+            // The index of the first global symbol
+            info: 1,
+            align: 4,
+            entry_size: SYMBOL_SIZE,
+        });
+        offset += symbol_table.len() as u64;
+
+        sections.push(SectionHeader {
+            name: add_string(&mut section_names, ".strtab"),
+            sh_type: SHT_STRTAB,
+            flags: 0,
+            address: 0,
+            offset: offset as u32,
+            size: string_table.len() as u32,
+            link: 0,
+            info: 0,
+            align: 1,
+            entry_size: 0,
+        });
+        offset += string_table.len() as u64;
+    }
+
+    let section_names_index = sections.len();
+    let name = add_string(&mut section_names, ".shstrtab");
+    sections.push(SectionHeader {
+        name,
+        sh_type: SHT_STRTAB,
+        flags: 0,
+        address: 0,
+        offset: offset as u32,
+        size: section_names.len() as u32,
+        link: 0,
+        info: 0,
+        align: 1,
+        entry_size: 0,
+    });
+    offset += section_names.len() as u64;
+    let section_headers_offset = align(offset, 4);
+
+    let mut header = Vec::with_capacity(ELF_HEADER_SIZE as usize);
+    header.extend_from_slice(ELF_MAGIC);
+    header.extend_from_slice(&[ELF_CLASS_32, ELF_DATA_BIG_ENDIAN, ELF_VERSION]);
+    header.resize(16, 0);
+    header.write_u16::<BigEndian>(ET_EXEC)?;
+    header.write_u16::<BigEndian>(EM_PPC)?;
+    header.write_u32::<BigEndian>(ELF_VERSION as u32)?;
+    header.write_u32::<BigEndian>(dol.entry_point as u32)?;
+    header.write_u32::<BigEndian>(ELF_HEADER_SIZE as u32)?;
+    header.write_u32::<BigEndian>(section_headers_offset as u32)?;
+    header.write_u32::<BigEndian>(0)?; // flags
+    header.write_u16::<BigEndian>(ELF_HEADER_SIZE)?;
+    header.write_u16::<BigEndian>(PROGRAM_HEADER_SIZE)?;
+    header.write_u16::<BigEndian>(program_header_count as u16)?;
+    header.write_u16::<BigEndian>(SECTION_HEADER_SIZE)?;
+    header.write_u16::<BigEndian>(sections.len() as u16)?;
+    header.write_u16::<BigEndian>(section_names_index as u16)?;
+
+    // One loadable segment for each section with data, plus the BSS
+    for section in sections.iter().skip(1).take(program_header_count) {
+        let is_bss = section.sh_type == SHT_NOBITS;
+        let mut flags = PF_R;
+        if section.flags & SHF_EXECINSTR != 0 { flags |= PF_X }
+        if section.flags & SHF_WRITE != 0 { flags |= PF_W }
+        header.write_u32::<BigEndian>(PT_LOAD)?;
+        header.write_u32::<BigEndian>(section.offset)?;
+        header.write_u32::<BigEndian>(section.address)?;
+        header.write_u32::<BigEndian>(section.address)?;
+        header.write_u32::<BigEndian>(if is_bss { 0 } else { section.size })?;
+        header.write_u32::<BigEndian>(section.size)?;
+        header.write_u32::<BigEndian>(flags)?;
+        header.write_u32::<BigEndian>(section.align)?;
+    }
+    header.resize(data_start as usize, 0);
+    writer.write_all(&header)?;
+
+    let mut position = data_start;
+    for (s, section) in segments.iter().zip(sections.iter().skip(1)) {
+        writer.write_all(&vec![0; (section.offset as u64 - position) as usize])?;
+        writer.write_all(&s.data)?;
+        position = section.offset as u64 + s.size() as u64;
+    }
+    let symbols_start = align(position, DATA_ALIGNMENT);
+    writer.write_all(&vec![0; (symbols_start - position) as usize])?;
+    writer.write_all(&symbol_table)?;
+    writer.write_all(&string_table)?;
+    writer.write_all(&section_names)?;
+    writer.write_all(&vec![0; (section_headers_offset - offset) as usize])?;
+
+    for section in &sections {
+        writer.write_u32::<BigEndian>(section.name)?;
+        writer.write_u32::<BigEndian>(section.sh_type)?;
+        writer.write_u32::<BigEndian>(section.flags)?;
+        writer.write_u32::<BigEndian>(section.address)?;
+        writer.write_u32::<BigEndian>(section.offset)?;
+        writer.write_u32::<BigEndian>(section.size)?;
+        writer.write_u32::<BigEndian>(section.link)?;
+        writer.write_u32::<BigEndian>(section.info)?;
+        writer.write_u32::<BigEndian>(section.align)?;
+        writer.write_u32::<BigEndian>(section.entry_size)?;
+    }
+    Ok(())
+}
@@ -1,17 +1,22 @@
 use std::collections::BTreeMap;
-use std::fs::{create_dir, File};
-use std::io::{self, BufRead, Seek};
-use std::path::Path;
+use std::fs::{self, File};
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+use std::path::{self, Path};
 
+use compression::yaz0;
+use layout::Layout;
 use sections::apploader::{Apploader, APPLOADER_OFFSET};
+use sections::banner::{Banner, BANNER_FILENAME};
 use sections::dol::DOLHeader;
 use sections::dol::segment::Segment;
 use sections::fst::{
-    entry::DirectoryEntry,
+    entry::{DirectoryEntry, Entry, FileEntry},
     FST,
 };
-use sections::header::{GAME_HEADER_SIZE, Header};
+use sections::header::{GAME_HEADER_SIZE, Header, Region};
+use sections::rel::REL;
 use ::{
+    extract_section,
     format_u64,
     NumberStyle,
     paths::*,
@@ -38,7 +43,7 @@ impl Game {
         let header = Header::new(&mut iso, offset)?;
         let apploader = Apploader::new(&mut iso, offset + APPLOADER_OFFSET)?;
         let dol = DOLHeader::new(&mut iso, offset + header.dol_offset)?;
-        let fst = FST::new(&mut iso, offset + header.fst_offset)?;
+        let fst = FST::new(&mut iso, offset + header.fst_offset, header.encoding())?;
 
         Ok(Game {
             header,
@@ -70,34 +75,45 @@ impl Game {
         ROMLayout(layout)
     }
 
-    pub fn extract<R, P>(&mut self, mut iso: R, path: P) -> io::Result<()>
+    // The offset of the end of the last section or file in the ROM.
+    pub fn space_used(&self) -> u64 {
+        self.rom_layout().0.iter()
+            .map(|s| s.start() + s.size() as u64)
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn extract<R, P>(&mut self, mut iso: R, path: P, layout: Layout) -> io::Result<()>
     where
         R: BufRead + Seek,
         P: AsRef<Path>,
     {
-        // Not using `create_dir_all` here so it fails if `path` already exists.
-        create_dir(path.as_ref())?;
-        let sys_data_path = path.as_ref().join("&&systemdata");
-        let sys_data_path: &Path = sys_data_path.as_ref();
-        create_dir(sys_data_path)?;
+        let path = path.as_ref();
+        // This fails if `path` already exists.
+        layout.create_dirs(path)?;
 
         println!("Extracting system data...");
 
-        let header_file = File::create(sys_data_path.join("ISO.hdr"))?;
-        Header::extract(&mut iso, header_file)?;
+        // The header may be split across more than one file
+        let header_files = layout.header_files(path);
+        for (i, &(offset, ref header_path)) in header_files.iter().enumerate() {
+            let end = header_files.get(i + 1).map_or(GAME_HEADER_SIZE as u64, |&(o, _)| o);
+            iso.seek(SeekFrom::Start(offset))?;
+            extract_section(&mut iso, (end - offset) as usize, File::create(header_path)?)?;
+        }
 
-        let fst_file = File::create(sys_data_path.join("Game.toc"))?;
+        let fst_file = File::create(layout.fst_path(path))?;
         FST::extract(&mut iso, fst_file, self.fst.offset)?;
 
-        let apploader_file = File::create(sys_data_path.join("Apploader.ldr"))?;
+        let apploader_file = File::create(layout.apploader_path(path))?;
         Apploader::extract(&mut iso, apploader_file)?;
 
-        let mut dol_file = File::create(sys_data_path.join("Start.dol"))?;
+        let mut dol_file = File::create(layout.dol_path(path))?;
         DOLHeader::extract(&mut iso, &mut dol_file, self.dol.offset)?;
 
         println!("Extracting file system...");
 
-        self.extract_file_system(&mut iso, path.as_ref(), 4)?;
+        self.extract_file_system(&mut iso, layout.files_path(path), 3 + header_files.len())?;
         Ok(())
     }
 
@@ -117,6 +133,49 @@ impl Game {
         res
     }
 
+    // Decompresses the Yaz0 files that `extract` wrote to `path`, and lists
+    // them so the rebuilder knows to compress them again. Returns how many
+    // files were decompressed.
+    pub fn decompress_yaz0_files<P>(&self, path: P, layout: Layout) -> io::Result<usize>
+    where
+        P: AsRef<Path>,
+    {
+        let files_path = layout.files_path(path.as_ref());
+        let mut list = String::new();
+        let mut count = 0;
+
+        for f in self.fst.entries.iter().filter_map(Entry::as_file) {
+            let relative_path = f.info.full_path.strip_prefix(path::MAIN_SEPARATOR.to_string())
+                .unwrap_or(&f.info.full_path);
+            let file_path = files_path.join(relative_path);
+            // Only the header is read until the file turns out to be Yaz0
+            let mut file = File::open(&file_path)?;
+            let mut data = Vec::with_capacity(yaz0::YAZ0_HEADER_SIZE);
+            (&mut file).take(yaz0::YAZ0_HEADER_SIZE as u64).read_to_end(&mut data)?;
+            if !yaz0::is_yaz0(&data) { continue }
+            file.read_to_end(&mut data)?;
+
+            let data = yaz0::decompress(&data)
+                .map_err(|e| io::Error::new(
+                    e.kind(),
+                    format!("Couldn't decompress {}: {}", relative_path.display(), e),
+                ))?;
+            fs::write(&file_path, data)?;
+
+            let names: Vec<_> = relative_path.iter().map(|n| n.to_string_lossy()).collect();
+            list.push_str(&names.join("/"));
+            list.push('\n');
+            count += 1;
+            print!("\r{} files decompressed.", count);
+        }
+
+        if count > 0 {
+            println!();
+            fs::write(layout.yaz0_list_path(path), list)?;
+        }
+        Ok(count)
+    }
+
     pub fn extract_section_with_name(
         &self,
         filename: impl AsRef<Path>,
@@ -162,10 +221,44 @@ impl Game {
         }
     }
 
-    pub fn print_info(&self, style: NumberStyle) {
+    // `rom_size` is the size of the decoded disc the game was read from. Only
+    // a plain ISO's size says whether it's been trimmed, since containers
+    // like CISO can leave out blocks at the end without the game missing
+    // anything. The banner has the game's full name, which the header's
+    // title is sometimes a shortened version of.
+    pub fn print_info(
+        &self,
+        rom_size: u64,
+        plain_iso: bool,
+        banner: Option<&Banner>,
+        style: NumberStyle,
+    ) {
         println!("Title: {}", self.header.title);
+        if let Some(banner) = banner {
+            let name = &banner.default_metadata().full_game_name;
+            if !name.is_empty() {
+                println!("Full name: {}", name);
+            }
+        }
         println!("GameID: {}{}", self.header.game_code, self.header.maker_code);
         println!("Version: {}", format_u64(self.header.version as u64, style));
+        println!("Region: {}", self.header.region().map(Region::name).unwrap_or("unknown"));
+
+        let space_used = self.space_used();
+        println!(
+            "Space used: {}/{} bytes",
+            format_u64(space_used, style),
+            format_u64(ROM_SIZE as u64, style),
+        );
+        if !plain_iso {
+            println!("Size: {} bytes", format_u64(rom_size, style));
+        } else if rom_size < space_used {
+            println!("Size: {} bytes (truncated, some files are missing)", format_u64(rom_size, style));
+        } else if rom_size < ROM_SIZE as u64 {
+            println!("Size: {} bytes (trimmed)", format_u64(rom_size, style));
+        } else {
+            println!("Size: {} bytes", format_u64(rom_size, style));
+        }
 
         println!("\nROM Layout:");
         self.print_layout();
@@ -191,10 +284,55 @@ impl Game {
         }
     }
 
-    pub fn print_directory(&self, dir: &DirectoryEntry, long_format: bool) {
+    // Reads `file` as a REL, if it looks like one
+    pub fn read_rel<R>(&self, iso: R, file: &FileEntry) -> Option<REL>
+    where
+        R: Read + Seek,
+    {
+        if REL::has_rel_extension(&file.info.name) {
+            REL::new(iso, file.file_offset, file.size).ok()
+        } else {
+            None
+        }
+    }
+
+    // Finds the REL file that contains `offset`, if there is one
+    pub fn rel_at_offset<R>(&self, iso: R, offset: u64) -> Option<REL>
+    where
+        R: Read + Seek,
+    {
+        self.fst.entries.iter()
+            .filter_map(Entry::as_file)
+            .find(|f| f.file_offset <= offset && offset < f.file_offset + f.size as u64)
+            .and_then(|f| self.read_rel(iso, f))
+    }
+
+    pub fn banner_file(&self) -> Option<&FileEntry> {
+        self.fst.root().iter_contents(&self.fst.entries)
+            .filter_map(Entry::as_file)
+            .find(|f| f.info.has_name(BANNER_FILENAME))
+    }
+
+    // Reads the banner from `opening.bnr`, if the game has one
+    pub fn read_banner<R>(&self, iso: R) -> Option<Banner>
+    where
+        R: Read + Seek,
+    {
+        self.banner_file()
+            .and_then(|f| Banner::new(iso, f.file_offset, self.header.encoding()).ok())
+    }
+
+    pub fn print_directory<R>(&self, mut iso: R, dir: &DirectoryEntry, long_format: bool)
+    where
+        R: Read + Seek,
+    {
         for e in dir.iter_contents(&self.fst.entries) {
             if long_format {
-                println!("{}", e.format_long());
+                let rel = e.as_file().and_then(|f| self.read_rel(&mut iso, f));
+                match rel {
+                    Some(rel) => println!("{}  (REL module {})", e.format_long(), rel.module_id),
+                    None => println!("{}", e.format_long()),
+                }
             } else {
                 println!("{}", e.info().full_path.to_string_lossy());
             }
@@ -0,0 +1,436 @@
+// Applies Gecko codes to a DOL ahead of time, so they work without a cheat
+// engine. Only the code types that write to a fixed address can be applied
+// like this, anything that depends on the state of the game while it's
+// running (conditionals, pointers, etc...) is reported as unsupported.
+// https://geckocodes.org/index.php?arsenal=1
+
+use std::collections::HashSet;
+use std::io::{self, BufRead};
+
+use byteorder::{BigEndian, ByteOrder};
+
+use disasm::AddressRange;
+use sections::dol::Dol;
+use sections::dol::segment::SegmentType;
+
+// A range of low memory the OS doesn't use, which is where Dolphin and
+// the Gecko code handler put themselves.
+pub const DEFAULT_CAVE: AddressRange = AddressRange { start: 0x8000_1800, end: 0x8000_3000 };
+
+const BASE_ADDRESS: u32 = 0x8000_0000;
+
+// Code types
+const WRITE_8: u8 = 0x00;
+const WRITE_16: u8 = 0x02;
+const WRITE_32: u8 = 0x04;
+const WRITE_STRING: u8 = 0x06;
+const INSERT_ASM: u8 = 0xC2;
+const FULL_TERMINATOR: u8 = 0xE0;
+const END_OF_CODES: u8 = 0xF0;
+
+// Set in the first byte of a code if it's relative to the pointer address
+const POINTER_FLAG: u8 = 0x10;
+
+#[derive(Clone, Debug)]
+pub struct GeckoCode {
+    pub name: String,
+    // Each line of a code is two 32-bit words
+    pub lines: Vec<(u32, u32)>,
+}
+
+// Reads Gecko codes from either a plain list, where each code has a name on
+// its own line followed by the code's lines, or a Dolphin game INI. For INI
+// files, only the `[Gecko]` section is read, and if there's a
+// `[Gecko_Enabled]` section, only the codes listed there are returned.
+pub fn parse_codes(reader: impl BufRead) -> io::Result<Vec<GeckoCode>> {
+    let mut codes: Vec<GeckoCode> = Vec::new();
+    let mut enabled = None;
+    let mut is_ini = false;
+    let mut section = String::new();
+
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('*') || line.starts_with('#') {
+            continue;
+        }
+
+        if line.starts_with('[') && line.ends_with(']') {
+            is_ini = true;
+            section = line[1..line.len() - 1].to_string();
+            if section == "Gecko_Enabled" {
+                enabled.get_or_insert_with(HashSet::new);
+            }
+            continue;
+        }
+
+        if section == "Gecko_Enabled" {
+            if let (Some(enabled), Some(name)) = (enabled.as_mut(), code_name(line)) {
+                enabled.insert(name);
+            }
+            continue;
+        }
+        if is_ini && section != "Gecko" {
+            continue;
+        }
+
+        match parse_line(line) {
+            Some(words) => match codes.last_mut() {
+                Some(code) => code.lines.push(words),
+                None => codes.push(GeckoCode { name: String::new(), lines: vec![words] }),
+            },
+            None => codes.push(GeckoCode {
+                name: code_name(line).unwrap_or_default(),
+                lines: Vec::new(),
+            }),
+        }
+    }
+
+    codes.retain(|c| !c.lines.is_empty());
+    if let Some(enabled) = enabled {
+        codes.retain(|c| enabled.contains(&c.name));
+    }
+    Ok(codes)
+}
+
+// Gets the name of a code from a line like `$Infinite Health [author]`
+fn code_name(line: &str) -> Option<String> {
+    let name = line.trim_start_matches('$');
+    let name = match name.find('[') {
+        Some(i) if name.ends_with(']') => &name[..i],
+        _ => name,
+    };
+    let name = name.trim();
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.to_string())
+    }
+}
+
+fn parse_line(line: &str) -> Option<(u32, u32)> {
+    let mut words = line.split_whitespace();
+    let first = words.next()?;
+    let second = words.next()?;
+    if words.next().is_some() || first.len() != 8 || second.len() != 8 {
+        return None;
+    }
+    Some((
+        u32::from_str_radix(first, 16).ok()?,
+        u32::from_str_radix(second, 16).ok()?,
+    ))
+}
+
+// What happened when applying a list of codes
+#[derive(Debug, Default)]
+pub struct PatchReport {
+    pub applied: Vec<String>,
+    // The name of each code that couldn't be applied, and why
+    pub skipped: Vec<(String, String)>,
+    // Where the inserted ASM was put, if there was any
+    pub cave: Option<(u64, u64)>,
+}
+
+// The changes a code makes, which are collected before anything
+// is written so a code is either applied entirely or not at all.
+#[derive(Default)]
+struct Patch {
+    writes: Vec<(u64, Vec<u8>)>,
+    // The address to branch from, and the code to branch to
+    hooks: Vec<(u64, Vec<u8>)>,
+    // Whether the code ended with F0, which stops the code handler, so
+    // nothing after it is run
+    end_of_codes: bool,
+}
+
+fn code_address(first: u32) -> u64 {
+    (BASE_ADDRESS | (first & 0x01FF_FFFF)) as u64
+}
+
+fn branch(from: u64, to: u64) -> [u8; 4] {
+    let mut bytes = [0; 4];
+    let delta = to.wrapping_sub(from) as u32;
+    BigEndian::write_u32(&mut bytes, 0x4800_0000 | (delta & 0x03FF_FFFC));
+    bytes
+}
+
+fn read_patch(code: &GeckoCode) -> Result<Patch, String> {
+    let mut patch = Patch::default();
+    let mut lines = code.lines.iter();
+
+    while let Some(&(first, second)) = lines.next() {
+        let code_type = (first >> 24) as u8;
+        let address = code_address(first);
+
+        // The lowest bit of the code type is part of the address
+        match code_type & !1 {
+            WRITE_8 => {
+                let count = (second >> 16) as usize + 1;
+                patch.writes.push((address, vec![second as u8; count]));
+            },
+            WRITE_16 => {
+                let count = (second >> 16) as usize + 1;
+                let mut data = vec![0; count * 2];
+                for chunk in data.chunks_mut(2) {
+                    BigEndian::write_u16(chunk, second as u16);
+                }
+                patch.writes.push((address, data));
+            },
+            WRITE_32 => {
+                let mut data = vec![0; 4];
+                BigEndian::write_u32(&mut data, second);
+                patch.writes.push((address, data));
+            },
+            WRITE_STRING => {
+                let size = second as usize;
+                let data = read_data(&mut lines, size.div_ceil(8))
+                    .ok_or_else(|| format!("The string write at {:08X} is cut off", first))?;
+                patch.writes.push((address, data[..size].to_vec()));
+            },
+            INSERT_ASM => {
+                let data = read_data(&mut lines, second as usize)
+                    .ok_or_else(|| format!("The ASM inserted at {:08X} is cut off", first))?;
+                if data.is_empty() {
+                    return Err(format!("The ASM inserted at {:08X} is empty", first));
+                }
+                patch.hooks.push((address, data));
+            },
+            FULL_TERMINATOR => (),
+            END_OF_CODES => {
+                patch.end_of_codes = true;
+                break;
+            },
+            t if t & POINTER_FLAG != 0 && t & !POINTER_FLAG <= WRITE_STRING =>
+                return Err(format!("{:08X} writes relative to the pointer address", first)),
+            t => return Err(format!("Code type {:02X} isn't supported", t)),
+        }
+    }
+
+    Ok(patch)
+}
+
+// Reads the data that follows a string write or inserted ASM
+fn read_data<'a>(lines: &mut impl Iterator<Item = &'a (u32, u32)>, count: usize) -> Option<Vec<u8>> {
+    let mut data = vec![0; count * 8];
+    for chunk in data.chunks_mut(8) {
+        let &(first, second) = lines.next()?;
+        BigEndian::write_u32(&mut chunk[..4], first);
+        BigEndian::write_u32(&mut chunk[4..], second);
+    }
+    Some(data)
+}
+
+fn check_write(dol: &Dol, address: u64, size: usize) -> Result<(), String> {
+    match dol.segment_at_addr(address) {
+        Some(s) if address + size as u64 <= s.end_address() => Ok(()),
+        Some(s) => Err(format!("The write to {:#010x} goes past the end of {}", address, s.name())),
+        None => Err(format!("{:#010x} isn't in the DOL", address)),
+    }
+}
+
+fn write(dol: &mut Dol, address: u64, data: &[u8]) {
+    let s = dol.segment_at_addr_mut(address).unwrap();
+    let start = (address - s.loading_address) as usize;
+    s.data[start..start + data.len()].copy_from_slice(data);
+}
+
+// Applies every code that can be applied to `dol`. Inserted ASM is put in
+// a new text segment at the start of `cave`. The instruction each piece of
+// ASM replaces becomes a branch to it, and the ASM ends by branching back.
+pub fn apply_codes(
+    dol: &mut Dol,
+    codes: &[GeckoCode],
+    cave: AddressRange,
+) -> io::Result<PatchReport> {
+    if dol.iter_segments().any(|s| s.loading_address < cave.end && cave.start < s.end_address()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("The code cave at {:#010x} overlaps a segment in the DOL", cave.start),
+        ));
+    }
+
+    let mut report = PatchReport::default();
+    let mut asm_segment = Vec::new();
+
+    for (i, code) in codes.iter().enumerate() {
+        let result = read_patch(code).and_then(|patch| {
+            let hook_writes = patch.hooks.iter().map(|&(a, _)| (a, 4));
+            for (address, size) in patch.writes.iter().map(|(a, d)| (*a, d.len())).chain(hook_writes) {
+                check_write(dol, address, size)?;
+            }
+            let asm_size: usize = patch.hooks.iter().map(|(_, asm)| asm.len()).sum();
+            if cave.start + (asm_segment.len() + asm_size) as u64 > cave.end {
+                return Err(format!(
+                    "There isn't enough room for its ASM between {:#010x} and {:#010x}",
+                    cave.start,
+                    cave.end,
+                ));
+            }
+            Ok(patch)
+        });
+
+        let patch = match result {
+            Ok(p) => p,
+            Err(reason) => {
+                report.skipped.push((code.name.clone(), reason));
+                continue;
+            },
+        };
+
+        for (address, data) in &patch.writes {
+            write(dol, *address, data);
+        }
+        for (hook, mut asm) in patch.hooks {
+            let start = cave.start + asm_segment.len() as u64;
+            let end = start + asm.len() as u64;
+            let asm_len = asm.len();
+            asm[asm_len - 4..].copy_from_slice(&branch(end - 4, hook + 4));
+            write(dol, hook, &branch(hook, start));
+            asm_segment.extend_from_slice(&asm);
+        }
+        report.applied.push(code.name.clone());
+
+        if patch.end_of_codes {
+            for c in &codes[i + 1..] {
+                report.skipped.push((c.name.clone(), "It comes after an F0 code, which ends the list".to_string()));
+            }
+            break;
+        }
+    }
+
+    if !asm_segment.is_empty() {
+        report.cave = Some((cave.start, cave.start + asm_segment.len() as u64));
+        dol.add_segment(SegmentType::Text, cave.start, asm_segment)?;
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT_ADDRESS: u64 = 0x8000_3100;
+    const DATA_ADDRESS: u64 = 0x8100_0000;
+
+    fn test_dol() -> Dol {
+        let mut dol = Dol::new(TEXT_ADDRESS);
+        dol.add_segment(SegmentType::Text, TEXT_ADDRESS, vec![0; 0x100]).unwrap();
+        dol.add_segment(SegmentType::Data, DATA_ADDRESS, vec![0; 0x20]).unwrap();
+        dol
+    }
+
+    fn apply(codes: &str) -> (Dol, PatchReport) {
+        let mut dol = test_dol();
+        let codes = parse_codes(codes.as_bytes()).unwrap();
+        let report = apply_codes(&mut dol, &codes, DEFAULT_CAVE).unwrap();
+        (dol, report)
+    }
+
+    fn read(dol: &Dol, address: u64, size: usize) -> &[u8] {
+        let s = dol.segment_at_addr(address).unwrap();
+        let start = (address - s.loading_address) as usize;
+        &s.data[start..start + size]
+    }
+
+    #[test]
+    fn parse_list_and_ini() {
+        let codes = parse_codes(&b"\
+            $First [someone]\n\
+            * A comment\n\
+            04003100 00000001\n\
+            Second\n\
+            04003104 00000002\n\
+            04003108 00000003\n"[..]).unwrap();
+        assert_eq!(codes.len(), 2);
+        assert_eq!(codes[0].name, "First");
+        assert_eq!(codes[0].lines, [(0x0400_3100, 1)]);
+        assert_eq!(codes[1].name, "Second");
+        assert_eq!(codes[1].lines.len(), 2);
+
+        let codes = parse_codes(&b"\
+            [Gecko]\n\
+            $First\n\
+            04003100 00000001\n\
+            $Second\n\
+            04003104 00000002\n\
+            [Gecko_Enabled]\n\
+            $Second\n\
+            [ActionReplay]\n\
+            04003108 00000003\n"[..]).unwrap();
+        assert_eq!(codes.len(), 1);
+        assert_eq!(codes[0].name, "Second");
+        assert_eq!(codes[0].lines, [(0x0400_3104, 2)]);
+    }
+
+    #[test]
+    fn fixed_writes() {
+        let (dol, report) = apply("\
+            $Writes\n\
+            00003100 00020012\n\
+            02003110 00011234\n\
+            04003120 DEADBEEF\n\
+            01000004 000000AB\n");
+        assert_eq!(report.applied, ["Writes"]);
+        // The top half of the second word is how many more times to write it
+        assert_eq!(read(&dol, 0x8000_3100, 4), [0x12, 0x12, 0x12, 0x00]);
+        assert_eq!(read(&dol, 0x8000_3110, 6), [0x12, 0x34, 0x12, 0x34, 0x00, 0x00]);
+        assert_eq!(read(&dol, 0x8000_3120, 4), [0xDE, 0xAD, 0xBE, 0xEF]);
+        // 01 is an 8-bit write with the 24th bit of the address set
+        assert_eq!(read(&dol, DATA_ADDRESS + 4, 1), [0xAB]);
+    }
+
+    #[test]
+    fn string_writes() {
+        // The data is padded to a whole line, but only `size` bytes are written
+        let (dol, report) = apply("\
+            $String\n\
+            06003130 00000005\n\
+            11223344 55667788\n\
+            $Cut off\n\
+            06003140 00000009\n\
+            11223344 55667788\n");
+        assert_eq!(report.applied, ["String"]);
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(report.skipped[0].0, "Cut off");
+        assert_eq!(read(&dol, 0x8000_3130, 6), [0x11, 0x22, 0x33, 0x44, 0x55, 0x00]);
+        assert_eq!(read(&dol, 0x8000_3140, 8), [0; 8]);
+    }
+
+    #[test]
+    fn insert_asm() {
+        let (dol, report) = apply("\
+            $ASM\n\
+            C2003140 00000002\n\
+            38600001 60000000\n\
+            60000000 00000000\n");
+        assert_eq!(report.applied, ["ASM"]);
+        assert_eq!(report.cave, Some((DEFAULT_CAVE.start, DEFAULT_CAVE.start + 0x10)));
+        // b 0x80001800
+        assert_eq!(read(&dol, 0x8000_3140, 4), [0x4B, 0xFF, 0xE6, 0xC0]);
+        // The last word of the ASM branches back to the instruction after
+        // the hook, at 0x80003144
+        assert_eq!(read(&dol, DEFAULT_CAVE.start, 0x10), [
+            0x38, 0x60, 0x00, 0x01, 0x60, 0x00, 0x00, 0x00,
+            0x60, 0x00, 0x00, 0x00, 0x48, 0x00, 0x19, 0x38,
+        ]);
+    }
+
+    #[test]
+    fn end_of_codes() {
+        let (dol, report) = apply("\
+            $First\n\
+            04003100 00000001\n\
+            $Second\n\
+            04003104 00000002\n\
+            F0000000 00000000\n\
+            04003108 00000003\n\
+            $Third\n\
+            0400310C 00000004\n");
+        assert_eq!(report.applied, ["First", "Second"]);
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(report.skipped[0].0, "Third");
+        assert_eq!(read(&dol, 0x8000_3104, 4), [0, 0, 0, 2]);
+        assert_eq!(read(&dol, 0x8000_3108, 8), [0; 8]);
+    }
+}
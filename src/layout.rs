@@ -0,0 +1,154 @@
+use std::fs::{create_dir, File, OpenOptions};
+use std::io::{self, BufReader, Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+
+use sections::header::{
+    GAME_HEADER_SIZE,
+    Header,
+    HEADER_INFORMATION_OFFSET,
+    HEADER_INFORMATION_SIZE,
+};
+use ::paths::*;
+
+// How the system data and files of an extracted ROM are arranged on disk
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Layout {
+    // `&&systemdata/ISO.hdr`, `Start.dol` and so on, with the files in the
+    // root next to them. This is what GCRebuilder uses.
+    GCRebuilder,
+    // `sys/boot.bin`, `main.dol` and so on, with the files in `files/`.
+    // Dolphin can boot a game from this, and the header is split in two at
+    // the disk header information.
+    Dolphin,
+}
+
+impl Layout {
+    pub fn parse(name: &str) -> Option<Layout> {
+        match name {
+            "gcrebuilder" => Some(Layout::GCRebuilder),
+            "dolphin" => Some(Layout::Dolphin),
+            _ => None,
+        }
+    }
+
+    // Dolphin's layout is the only one with a `sys/boot.bin`
+    pub fn detect(root: impl AsRef<Path>) -> Layout {
+        if root.as_ref().join(DOLPHIN_BOOT_PATH).is_file() {
+            Layout::Dolphin
+        } else {
+            Layout::GCRebuilder
+        }
+    }
+
+    pub fn system_data_dir(self) -> &'static str {
+        match self {
+            Layout::GCRebuilder => "&&systemdata",
+            Layout::Dolphin => "sys",
+        }
+    }
+
+    pub fn apploader_path(self, root: impl AsRef<Path>) -> PathBuf {
+        root.as_ref().join(match self {
+            Layout::GCRebuilder => APPLOADER_PATH,
+            Layout::Dolphin => DOLPHIN_APPLOADER_PATH,
+        })
+    }
+
+    pub fn dol_path(self, root: impl AsRef<Path>) -> PathBuf {
+        root.as_ref().join(match self {
+            Layout::GCRebuilder => DOL_PATH,
+            Layout::Dolphin => DOLPHIN_DOL_PATH,
+        })
+    }
+
+    pub fn elf_path(self, root: impl AsRef<Path>) -> PathBuf {
+        root.as_ref().join(match self {
+            Layout::GCRebuilder => ELF_PATH,
+            Layout::Dolphin => DOLPHIN_ELF_PATH,
+        })
+    }
+
+    // The DOL that goes in the ROM, which may actually be an ELF file. The
+    // ELF path is only used if there isn't a DOL.
+    pub fn find_dol(self, root: impl AsRef<Path>) -> PathBuf {
+        let dol_path = self.dol_path(root.as_ref());
+        let elf_path = self.elf_path(root.as_ref());
+        if !dol_path.exists() && elf_path.exists() {
+            elf_path
+        } else {
+            dol_path
+        }
+    }
+
+    pub fn fst_path(self, root: impl AsRef<Path>) -> PathBuf {
+        root.as_ref().join(match self {
+            Layout::GCRebuilder => FST_PATH,
+            Layout::Dolphin => DOLPHIN_FST_PATH,
+        })
+    }
+
+    // The list of files that were decompressed when the ROM was extracted,
+    // one per line, relative to `files_path`
+    pub fn yaz0_list_path(self, root: impl AsRef<Path>) -> PathBuf {
+        root.as_ref().join(match self {
+            Layout::GCRebuilder => YAZ0_LIST_PATH,
+            Layout::Dolphin => DOLPHIN_YAZ0_LIST_PATH,
+        })
+    }
+
+    // The directory that the ROM's file system is in
+    pub fn files_path(self, root: impl AsRef<Path>) -> PathBuf {
+        match self {
+            Layout::GCRebuilder => root.as_ref().to_path_buf(),
+            Layout::Dolphin => root.as_ref().join(DOLPHIN_FILES_PATH),
+        }
+    }
+
+    // The files the header is stored in, and where each one goes in the ROM
+    pub fn header_files(self, root: impl AsRef<Path>) -> Vec<(u64, PathBuf)> {
+        let root = root.as_ref();
+        match self {
+            Layout::GCRebuilder => vec![(0, root.join(HEADER_PATH))],
+            Layout::Dolphin => vec![
+                (0, root.join(DOLPHIN_BOOT_PATH)),
+                (HEADER_INFORMATION_OFFSET as u64, root.join(DOLPHIN_BI2_PATH)),
+            ],
+        }
+    }
+
+    // Creates the directories that `root`'s system data and files go in.
+    // This fails if `root` already exists.
+    pub fn create_dirs(self, root: impl AsRef<Path>) -> io::Result<()> {
+        let root = root.as_ref();
+        create_dir(root)?;
+        create_dir(root.join(self.system_data_dir()))?;
+        if self == Layout::Dolphin {
+            create_dir(root.join(DOLPHIN_FILES_PATH))?;
+        }
+        Ok(())
+    }
+
+    pub fn read_header(self, root: impl AsRef<Path>) -> io::Result<Header> {
+        let mut buf = Vec::with_capacity(GAME_HEADER_SIZE);
+        for (_, path) in self.header_files(root) {
+            File::open(path)?.read_to_end(&mut buf)?;
+        }
+        Header::new(BufReader::new(Cursor::new(buf)), 0)
+    }
+
+    // Writes `header` over the header file(s) in `root`, up to the end of the
+    // disk header information. Anything after that is left as it is.
+    pub fn write_header(self, root: impl AsRef<Path>, header: &Header) -> io::Result<()> {
+        let mut buf = Vec::with_capacity(GAME_HEADER_SIZE);
+        header.write(&mut buf)?;
+        let buf = &buf[..HEADER_INFORMATION_OFFSET + HEADER_INFORMATION_SIZE];
+
+        let files = self.header_files(root);
+        for (i, &(offset, ref path)) in files.iter().enumerate() {
+            let end = files.get(i + 1).map_or(buf.len(), |&(o, _)| o as usize);
+            OpenOptions::new().write(true).open(path)?
+                .write_all(&buf[offset as usize..end])?;
+        }
+        Ok(())
+    }
+}
@@ -1,7 +1,14 @@
 extern crate byteorder;
+extern crate bzip2;
+extern crate encoding_rs;
+extern crate flate2;
 #[macro_use]
 extern crate lazy_static;
+extern crate lzma_rs;
+extern crate png;
 extern crate regex;
+extern crate ruzstd;
+extern crate tempfile;
 
 use std::borrow::Cow;
 use std::cmp::min;
@@ -9,11 +16,21 @@ use std::fmt;
 use std::io::{self, Read, Write};
 use std::num::ParseIntError;
 
+pub mod compression;
+pub mod container;
+pub mod disasm;
+pub mod elf;
+pub mod gecko;
+pub mod layout;
+
 mod game;
 pub use game::Game;
 pub use game::ROM_SIZE;
 
+pub mod memory;
 pub mod sections;
+pub mod symbols;
+pub mod text;
 
 mod rom_rebuilder;
 pub use rom_rebuilder::ROMRebuilder;
@@ -28,8 +45,23 @@ pub const MIN_ALIGNMENT: u64 = 4;
 pub mod paths {
     pub const APPLOADER_PATH: &'static str = "&&systemdata/Apploader.ldr";
     pub const DOL_PATH: &'static str = "&&systemdata/Start.dol";
+    // Used by the rebuilder instead of `DOL_PATH` if that doesn't exist
+    pub const ELF_PATH: &str = "&&systemdata/Start.elf";
     pub const FST_PATH: &'static str = "&&systemdata/Game.toc";
     pub const HEADER_PATH: &'static str = "&&systemdata/ISO.hdr";
+    // The files that `extract --decompress` decompressed, which the
+    // rebuilder compresses again
+    pub const YAZ0_LIST_PATH: &str = "&&systemdata/Yaz0.txt";
+
+    // The layout Dolphin boots extracted games from, see `layout::Layout`
+    pub const DOLPHIN_APPLOADER_PATH: &str = "sys/apploader.img";
+    pub const DOLPHIN_BI2_PATH: &str = "sys/bi2.bin";
+    pub const DOLPHIN_BOOT_PATH: &str = "sys/boot.bin";
+    pub const DOLPHIN_DOL_PATH: &str = "sys/main.dol";
+    pub const DOLPHIN_ELF_PATH: &str = "sys/main.elf";
+    pub const DOLPHIN_FILES_PATH: &str = "files";
+    pub const DOLPHIN_FST_PATH: &str = "sys/fst.bin";
+    pub const DOLPHIN_YAZ0_LIST_PATH: &str = "sys/yaz0.txt";
 }
 
 pub fn extract_section(
@@ -3,47 +3,157 @@ extern crate clap;
 extern crate gcmod;
 extern crate tempfile;
 
-use std::fs::{remove_file, File};
-use std::io::BufReader;
-use std::path::Path;
+use std::fs::{remove_file, File, OpenOptions};
+use std::io::{self, BufReader, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 
-use clap::AppSettings;
+use clap::{AppSettings, Values};
 
 use gcmod::{
     AppError,
     AppResult,
+    container::{
+        ciso::DEFAULT_CISO_BLOCK_SIZE,
+        ContainerFormat,
+        open_disc_image,
+        ReadSeek,
+    },
     DEFAULT_ALIGNMENT,
+    disasm::{AddressRange, disassemble, disassemble_segment},
+    elf::{dol_to_elf, Elf, elf_to_dol},
+    gecko::{apply_codes, DEFAULT_CAVE, parse_codes},
+    layout::Layout,
+    memory::{self, DEFAULT_MAX_STRING_LEN, Endianness, mem1_image, Value, ValueType},
     Game,
     format_u64,
     format_usize,
     MIN_ALIGNMENT,
     NumberStyle,
     parse_as_u64,
+    parse_as_usize,
     ROM_SIZE,
     sections::{
-        apploader::Apploader,
-        dol::DOLHeader,
+        apploader::{Apploader, APPLOADER_LOAD_ADDRESS, APPLOADER_OFFSET},
+        banner::{
+            Banner,
+            BANNER_FILENAME,
+            BANNER_HEIGHT,
+            BANNER_WIDTH,
+            BannerField,
+            BannerVersion,
+            decode_png,
+            LANGUAGES,
+        },
+        dol::{
+            Dol,
+            DOLHeader,
+            segment::{Segment, SegmentType},
+        },
         fst::FST,
-        header::Header,
+        header::{Header, Region},
+        rel::REL,
         Section,
+        tgc::{TGCHeader, TGCReader},
     },
+    symbols::SymbolMap,
+    text::TextEncoding,
 };
 use gcmod::ROMRebuilder;
 
 fn main() -> AppResult {
     let app = clap_app!(app =>
+        (@subcommand apply_codes =>
+            (name: "apply-codes")
+            (about: "Applies Gecko codes directly to a DOL file, so they work without a cheat engine.")
+            (@arg codes: +required "A list of Gecko codes, or a Dolphin game INI file with a [Gecko] section.")
+            (@arg dol: +required "A DOL file, or the root of an extracted ROM.")
+            (@arg output: -o --output +takes_value "Write the patched DOL here instead of replacing the original.")
+            (@arg cave: --cave +takes_value
+                "The range of memory to put inserted ASM (C2 codes) in, such as 0x80001800..0x80003000, which is the default.")
+        )
+        (@subcommand apploader =>
+            (about: "Works with apploaders, the code that loads a game's DOL.")
+            (@setting SubcommandRequiredElseHelp)
+            (@subcommand build =>
+                (about: "Builds an apploader from its code, which can then be put in the &&systemdata directory of an extracted ROM.")
+                (@arg output: +required)
+                (@arg code: -c --code +takes_value +required "A binary file with the code, which is loaded at 0x81200000.")
+                (@arg entry: -e --entry +takes_value +required "The address of the entry point, which has to be in the code.")
+                (@arg date: -d --date +takes_value +required "The build date of the apploader, such as 2001/08/23.")
+                (@arg trailer: -t --trailer +takes_value "A binary file to put after the code.")
+            )
+        )
+        (@subcommand banner =>
+            (about: "Shows the banner of a ROM, which has the game's full name and an image, and can save the image as a PNG.")
+            (@setting SubcommandsNegateReqs)
+            (@arg input: +required "A ROM, the root of an extracted ROM, or a banner file.")
+            (@arg png: -o --png +takes_value "Save the banner's image as a PNG file here.")
+            (@arg tgc: --tgc +takes_value "The path of a TGC file inside the ROM to use instead of the ROM itself.")
+            (@subcommand set =>
+                (about: "Changes the image or text of a banner, in the root of an extracted ROM, a banner file, or in place in a plain ISO.")
+                (@arg input: +required "A plain ISO, the root of an extracted ROM, or a banner file, which is created if it doesn't exist.")
+                (@arg image: -i --image +takes_value "A 96x32 PNG to use as the banner's image.")
+                (@arg name: --name +takes_value "The game's name.")
+                (@arg developer: --developer +takes_value "The developer's name.")
+                (@arg full_name: --("full-name") +takes_value "The game's full name, which is shown in Dolphin's game list.")
+                (@arg full_developer: --("full-developer") +takes_value "The developer's full name.")
+                (@arg description: --description +takes_value "A description of the game.")
+                (@arg language: --language +takes_value
+                    "Only change the text for this language in a BNR2 banner, such as german. By default, every language is changed.")
+            )
+        )
+        (@subcommand disasm =>
+            (about: "Disassemble the main DOL file from a ROM.")
+            (@arg rom_path: +required)
+            (@arg segment: -s --segment +takes_value +multiple number_of_values(1)
+                "Only disassemble the given DOL segment, such as .text0. Can be passed more than once. By default, every text segment is disassembled.")
+            (@arg range: -r --range +takes_value
+                "Only disassemble the instructions between two virtual addresses, such as 0x80003100..0x80003200.")
+            (@arg apploader: --apploader conflicts_with[segment]
+                "Disassemble the apploader's code instead of the DOL.")
+        )
+        (@subcommand dol2elf =>
+            (about: "Converts the main DOL file from a ROM, or a DOL file on its own, into a PowerPC ELF file.")
+            (@arg input: +required "A ROM, a DOL file, or the root of an extracted ROM.")
+            (@arg output: +required)
+            (@arg map: -m --map +takes_value "A CodeWarrior or Dolphin symbol map to add to the ELF file's symbol table.")
+        )
+        (@subcommand elf2dol =>
+            (about: "Converts a PowerPC ELF file into a DOL file.")
+            (@arg elf_path: +required)
+            (@arg output: +required)
+        )
         (@subcommand extract =>
             (about: "Extract a ROM's contents to disk.")
             (@arg rom_path: +required)
             (@arg output: +required)
             (@arg rom_section: -s --section +takes_value "Specify a single section to extract from the ROM, rather than everything.")
+            (@arg tgc: --tgc +takes_value "The path of a TGC file inside the ROM to use instead of the ROM itself.")
+            (@arg layout: --layout +takes_value possible_value[gcrebuilder dolphin]
+                "How to lay out the extracted files. gcrebuilder (the default) puts the system data in &&systemdata, dolphin uses the sys/ and files/ layout that Dolphin can boot. Both can be rebuilt.")
+            (@arg decompress: --decompress
+                "Decompress Yaz0 files. They're listed in the system data, and rebuilding the ROM compresses them again.")
+        )
+        (@subcommand header =>
+            (about: "Works with the header of a ROM, which has its title and game ID.")
+            (@setting SubcommandRequiredElseHelp)
+            (@subcommand set =>
+                (about: "Changes the header of a ROM in place, or of the root of an extracted ROM.")
+                (@arg rom_path: +required "A plain ISO, or the root of an extracted ROM.")
+                (@arg title: --title +takes_value "The game's title.")
+                (@arg game_id: --("game-id") +takes_value
+                    "The six character game ID, such as GALE01. Mods should use their own, so their saves and Dolphin settings don't clash with the original game's.")
+                (@arg maker: --maker +takes_value conflicts_with[game_id] "Just the last two characters of the game ID.")
+                (@arg disc: --disc +takes_value "The disc number, starting from 1.")
+                (@arg version: --version +takes_value "The version number of the game, starting from 0.")
+            )
         )
         (@subcommand info =>
             (about: "Display information about the ROM.")
             (@arg rom_path: +required)
             (@arg hex_output: -h --hex "Displays numbers in hexadecimal.")
             (@arg type: -t --type +takes_value +case_insensitive
-                possible_value[header dol fst apploader layout]
+                possible_value[header dol fst apploader layout tgc]
                 "Print a given type of information about the ROM.")
             (@arg offset: -o --offset +takes_value
                 conflicts_with[type mem_addr]
@@ -51,6 +161,14 @@ fn main() -> AppResult {
             (@arg mem_addr: -m --("mem-addr") +takes_value
                 conflicts_with[type offset]
                 "Print information about the DOL segment that will be loaded into a given address in memory.")
+            (@arg rel: --rel +takes_value
+                conflicts_with[type offset mem_addr]
+                "Print information about the REL file at the given path in the ROM.")
+            (@arg load_address: --("load-address") +takes_value requires[rel]
+                "Show where each part of the REL given to --rel would be if it were loaded at this address.")
+            (@arg map: --map +takes_value requires[mem_addr]
+                "A CodeWarrior or Dolphin symbol map, used to print which symbol is at the address given to --mem-addr.")
+            (@arg tgc: --tgc +takes_value "The path of a TGC file inside the ROM to use instead of the ROM itself.")
         )
         // TODO: add flags for searching and crap
         // Add more `ls` style flags (LS_COLORS!)
@@ -60,6 +178,40 @@ fn main() -> AppResult {
             (@arg rom_path: +required)
             (@arg dir: "The name or path of the directory in the ROM to list.")
             (@arg long: -l --long "List the files in an `ls -l`-style format.")
+            (@arg tgc: --tgc +takes_value "The path of a TGC file inside the ROM to use instead of the ROM itself.")
+        )
+        (@subcommand memimage =>
+            (about: "Builds a 24 MiB image of main memory as it would be when the game starts, for loading into a disassembler or comparing to a RAM dump.")
+            (@arg rom_path: +required)
+            (@arg output: +required)
+            (@arg tgc: --tgc +takes_value "The path of a TGC file inside the ROM to use instead of the ROM itself.")
+        )
+        (@subcommand peek =>
+            (about: "Reads values at an address in memory from the main DOL file.")
+            (@arg rom_path: +required "A ROM, a DOL file, or the root of an extracted ROM.")
+            (@arg address: +required)
+            (@arg count: "How many values to read. For strings, this is the maximum length. The default is 1, or 256 for strings.")
+            (@arg type: -t --type +takes_value +case_insensitive
+                possible_value[u8 u16 u32 f32 string]
+                "The type of value to read. The default is u32.")
+            (@arg little_endian: -l --("little-endian") "Read values as little endian rather than big endian.")
+            (@arg hex_output: -h --hex "Displays numbers in hexadecimal.")
+        )
+        (@subcommand poke =>
+            (about: "Writes values at an address in memory to the main DOL file, either in place in a ROM or in an extracted DOL.")
+            (@setting AllowNegativeNumbers)
+            (@arg rom_path: +required "A plain ISO, a DOL file, or the root of an extracted ROM.")
+            (@arg address: +required)
+            (@arg values: +required +multiple "The values to write, one after another. Strings have a null byte added to the end.")
+            (@arg type: -t --type +takes_value +case_insensitive
+                possible_value[u8 u16 u32 f32 string]
+                "The type of the values. The default is u32.")
+            (@arg little_endian: -l --("little-endian") "Write values as little endian rather than big endian.")
+        )
+        (@subcommand region =>
+            (about: "Changes the region of a ROM in place, or of the root of an extracted ROM.")
+            (@arg rom_path: +required "A plain ISO, or the root of an extracted ROM.")
+            (@arg to: --to +takes_value +required "The region to change to: pal, ntsc-u, or ntsc-j.")
         )
         (@subcommand rebuild =>
             (about: "Rebuilds a ROM.")
@@ -68,33 +220,198 @@ fn main() -> AppResult {
             (@arg no_rebuild_fst: --("no-rebuild-fst") "It this flag is passed, the existing file system table will be used, rather than creating a new one.")
             (@arg alignment: -a --alignment +takes_value
                 "Specifies the alignment in bytes for the files in the filesystem. The default is 32768 bytes (32KiB) and the minimum is 2 bytes.")
+            (@arg ciso: --ciso "Write the ROM as a CISO file, which leaves out blocks that only contain padding.")
+            (@arg trim: --trim "End the ROM after the last file, rather than padding it to the size of a full disc.")
+        )
+        (@subcommand rel =>
+            (about: "Works with REL files, the modules games load code from.")
+            (@setting SubcommandRequiredElseHelp)
+            (@subcommand link =>
+                (about: "Applies a REL's relocations as if it were loaded at a given address, and saves the result.")
+                (@arg rom_path: +required)
+                (@arg rel: +required "The path of the REL file in the ROM.")
+                (@arg output: +required)
+                (@arg base: -b --base +takes_value +required "The address to load the REL at.")
+                (@arg bss: --bss +takes_value "The address of the REL's BSS. By default, it goes right after the REL.")
+                (@arg module: -m --module +takes_value +multiple number_of_values(1)
+                    "Another REL this one imports from, given as path@address, like rels/other.rel@0x80600000. Can be passed more than once.")
+                (@arg elf: --elf "Save the REL as an ELF file rather than a memory image.")
+            )
+        )
+        (@subcommand sym =>
+            (about: "Works with symbol maps from CodeWarrior or Dolphin.")
+            (@setting SubcommandRequiredElseHelp)
+            (@subcommand lookup =>
+                (about: "Finds a symbol by name, or the symbol at an address.")
+                (@arg map: +required)
+                (@arg symbol: +required "The name of a symbol, or an address.")
+            )
+            (@subcommand export =>
+                (about: "Saves a symbol map in the format Dolphin uses, optionally making some changes to it.")
+                (@arg map: +required)
+                (@arg output: +required)
+                (@arg rename: --rename +takes_value +multiple number_of_values(1)
+                    "Renames a symbol, given as old=new. Can be passed more than once.")
+                (@arg remove: --remove +takes_value +multiple number_of_values(1)
+                    "Leaves out the symbol with the given name. Can be passed more than once.")
+            )
+        )
+        (@subcommand tgc2iso =>
+            (about: "Converts a TGC file into a bootable ROM.")
+            (@arg tgc_path: +required "A TGC file, or a ROM containing one if --tgc is passed.")
+            (@arg output: +required)
+            (@arg tgc: --tgc +takes_value "The path of the TGC file inside the ROM to convert.")
+            (@arg trim: --trim "End the ROM after the last file, rather than padding it to the size of a full disc.")
         )
     ).setting(AppSettings::SubcommandRequired);
 
     match app.get_matches().subcommand() {
+        ("apply-codes", Some(cmd)) =>
+            patch_dol(
+                cmd.value_of("codes").unwrap(),
+                cmd.value_of("dol").unwrap(),
+                cmd.value_of("output"),
+                cmd.value_of("cave"),
+            ),
+        ("apploader", Some(cmd)) => match cmd.subcommand() {
+            ("build", Some(cmd)) =>
+                build_apploader(
+                    cmd.value_of("output").unwrap(),
+                    cmd.value_of("code").unwrap(),
+                    cmd.value_of("entry").unwrap(),
+                    cmd.value_of("date").unwrap(),
+                    cmd.value_of("trailer"),
+                ),
+            _ => unreachable!(),
+        },
+        ("banner", Some(cmd)) => match cmd.subcommand() {
+            ("set", Some(cmd)) => {
+                let fields = [
+                    (BannerField::GameName, "name"),
+                    (BannerField::Developer, "developer"),
+                    (BannerField::FullGameName, "full_name"),
+                    (BannerField::FullDeveloper, "full_developer"),
+                    (BannerField::Description, "description"),
+                ];
+                set_banner(
+                    cmd.value_of("input").unwrap(),
+                    cmd.value_of("image"),
+                    fields.iter().filter_map(|&(f, arg)| cmd.value_of(arg).map(|text| (f, text))).collect(),
+                    cmd.value_of("language"),
+                )
+            },
+            _ => show_banner(
+                cmd.value_of("input").unwrap(),
+                cmd.value_of("png"),
+                cmd.value_of("tgc"),
+            ),
+        },
+        ("disasm", Some(cmd)) => {
+            let rom_path = cmd.value_of("rom_path").unwrap();
+            let range = cmd.value_of("range");
+            if cmd.is_present("apploader") {
+                disassemble_apploader(rom_path, range)
+            } else {
+                disassemble_dol(rom_path, cmd.values_of("segment"), range)
+            }
+        },
+        ("dol2elf", Some(cmd)) =>
+            convert_dol(
+                cmd.value_of("input").unwrap(),
+                cmd.value_of("output").unwrap(),
+                cmd.value_of("map"),
+            ),
+        ("elf2dol", Some(cmd)) =>
+            convert_elf(
+                cmd.value_of("elf_path").unwrap(),
+                cmd.value_of("output").unwrap(),
+            ),
         ("extract", Some(cmd)) => 
             extract_iso(
                 cmd.value_of("rom_path").unwrap(),
                 cmd.value_of("output").unwrap(),
                 cmd.value_of("rom_section"),
+                cmd.value_of("tgc"),
+                cmd.value_of("layout"),
+                cmd.is_present("decompress"),
             ),
-        ("info", Some(cmd)) => 
-            get_info(
+        ("header", Some(cmd)) => match cmd.subcommand() {
+            ("set", Some(cmd)) =>
+                set_header(
+                    cmd.value_of("rom_path").unwrap(),
+                    cmd.value_of("title"),
+                    cmd.value_of("game_id"),
+                    cmd.value_of("maker"),
+                    cmd.value_of("disc"),
+                    cmd.value_of("version"),
+                ),
+            _ => unreachable!(),
+        },
+        ("info", Some(cmd)) => {
+            let style = if cmd.is_present("hex_output") {
+                NumberStyle::Hexadecimal
+            } else {
+                NumberStyle::Decimal
+            };
+            match cmd.value_of("rel") {
+                Some(rel) =>
+                    print_rel_info(
+                        cmd.value_of("rom_path").unwrap(),
+                        rel,
+                        cmd.value_of("load_address"),
+                        cmd.value_of("tgc"),
+                        style,
+                    ),
+                None =>
+                    get_info(
+                        cmd.value_of("rom_path").unwrap(),
+                        cmd.value_of("type"),
+                        cmd.value_of("offset"),
+                        cmd.value_of("mem_addr"),
+                        cmd.value_of("map"),
+                        cmd.value_of("tgc"),
+                        style,
+                    ),
+            }
+        },
+        ("ls", Some(cmd)) =>
+            ls_files(
+                cmd.value_of("rom_path").unwrap(),
+                cmd.value_of("dir"),
+                cmd.is_present("long"),
+                cmd.value_of("tgc"),
+            ),
+        ("memimage", Some(cmd)) =>
+            build_memory_image(
+                cmd.value_of("rom_path").unwrap(),
+                cmd.value_of("output").unwrap(),
+                cmd.value_of("tgc"),
+            ),
+        ("peek", Some(cmd)) =>
+            peek_memory(
                 cmd.value_of("rom_path").unwrap(),
+                cmd.value_of("address").unwrap(),
+                cmd.value_of("count"),
                 cmd.value_of("type"),
-                cmd.value_of("offset"),
-                cmd.value_of("mem_addr"),
+                cmd.is_present("little_endian"),
                 if cmd.is_present("hex_output") {
                     NumberStyle::Hexadecimal
                 } else {
                     NumberStyle::Decimal
                 },
             ),
-        ("ls", Some(cmd)) =>
-            ls_files(
+        ("poke", Some(cmd)) =>
+            poke_memory(
                 cmd.value_of("rom_path").unwrap(),
-                cmd.value_of("dir"),
-                cmd.is_present("long"),
+                cmd.value_of("address").unwrap(),
+                cmd.values_of("values").unwrap(),
+                cmd.value_of("type"),
+                cmd.is_present("little_endian"),
+            ),
+        ("region", Some(cmd)) =>
+            convert_region(
+                cmd.value_of("rom_path").unwrap(),
+                cmd.value_of("to").unwrap(),
             ),
         ("rebuild", Some(cmd)) =>
             rebuild_iso(
@@ -102,6 +419,43 @@ fn main() -> AppResult {
                 cmd.value_of("output").unwrap(),
                 cmd.value_of("alignment"),
                 !cmd.is_present("no_rebuild_fst"),
+                cmd.is_present("ciso"),
+                cmd.is_present("trim"),
+            ),
+        ("rel", Some(cmd)) => match cmd.subcommand() {
+            ("link", Some(cmd)) =>
+                link_rel(
+                    cmd.value_of("rom_path").unwrap(),
+                    cmd.value_of("rel").unwrap(),
+                    cmd.value_of("output").unwrap(),
+                    cmd.value_of("base").unwrap(),
+                    cmd.value_of("bss"),
+                    cmd.values_of("module"),
+                    cmd.is_present("elf"),
+                ),
+            _ => unreachable!(),
+        },
+        ("sym", Some(cmd)) => match cmd.subcommand() {
+            ("lookup", Some(cmd)) =>
+                lookup_symbol(
+                    cmd.value_of("map").unwrap(),
+                    cmd.value_of("symbol").unwrap(),
+                ),
+            ("export", Some(cmd)) =>
+                export_symbols(
+                    cmd.value_of("map").unwrap(),
+                    cmd.value_of("output").unwrap(),
+                    cmd.values_of("rename"),
+                    cmd.values_of("remove"),
+                ),
+            _ => unreachable!(),
+        },
+        ("tgc2iso", Some(cmd)) =>
+            convert_tgc(
+                cmd.value_of("tgc_path").unwrap(),
+                cmd.value_of("output").unwrap(),
+                cmd.value_of("tgc"),
+                cmd.is_present("trim"),
             ),
         _ => unreachable!(),
     }
@@ -111,24 +465,49 @@ fn extract_iso(
     input: impl AsRef<Path>,
     output: impl AsRef<Path>,
     file_in_iso: Option<impl AsRef<Path>>,
+    tgc: Option<&str>,
+    layout: Option<&str>,
+    decompress: bool,
 ) -> AppResult {
     let output = output.as_ref();
 
     if let Some(file) = file_in_iso {
-        return extract_section(input.as_ref(), file.as_ref(), output);
+        return extract_section(input.as_ref(), file.as_ref(), output, tgc);
     }
+    let layout = layout.map_or(Some(Layout::GCRebuilder), Layout::parse)
+        .ok_or_else(|| AppError::new("Invalid layout. Must be gcrebuilder or dolphin."))?;
 
     if output.exists() {
         return Err(AppError::new(format!("Error: {} already exists.", output.display())));
     }
 
-    let (mut game, mut iso) = try_to_open_game(input.as_ref(), 0)?;
-    game.extract(&mut iso, output).map_err(|_| AppError::new("Failed to write files."))
+    let (mut game, mut iso) = try_to_open_game(input.as_ref(), 0, tgc)?;
+    game.extract(&mut iso, output, layout).map_err(|_| AppError::new("Failed to write files."))?;
+
+    if decompress {
+        let count = game.decompress_yaz0_files(output, layout)
+            .map_err(|e| AppError::new(format!("Failed to decompress files: {}", e)))?;
+        if count == 0 {
+            println!("There weren't any Yaz0 files to decompress.");
+        }
+    }
+    Ok(())
 }
 
-fn print_iso_info(input: impl AsRef<Path>, offset: u64, style: NumberStyle) -> AppResult {
-    let (game, _) = try_to_open_game(input, offset)?;
-    game.print_info(style);
+fn print_iso_info(
+    input: impl AsRef<Path>,
+    offset: u64,
+    tgc: Option<&str>,
+    style: NumberStyle,
+) -> AppResult {
+    let (game, mut iso) = try_to_open_game(&input, offset, tgc)?;
+    // Seeking goes by the decoded disc, not the container file
+    let rom_size = iso.seek(SeekFrom::End(0))?.saturating_sub(offset);
+    // A game at an offset or in a TGC file could have anything after it
+    let plain_iso = offset == 0 && tgc.is_none() &&
+        ContainerFormat::detect(File::open(input.as_ref())?)? == ContainerFormat::Iso;
+    let banner = game.read_banner(&mut iso);
+    game.print_info(rom_size, plain_iso, banner.as_ref(), style);
     Ok(())
 }
 
@@ -137,6 +516,8 @@ fn rebuild_iso(
     iso_path: impl AsRef<Path>,
     alignment: Option<&str>,
     rebuild_systemdata: bool,
+    ciso: bool,
+    trim: bool,
 ) -> AppResult {
     let alignment = match alignment {
         Some(a) => match parse_as_u64(a) {
@@ -149,17 +530,40 @@ fn rebuild_iso(
     let iso_path = iso_path.as_ref();
     let root_path = root_path.as_ref();
 
-    if iso_path.exists() {
+    // Things like pipes and /dev/stdout already exist, but are fine to write to
+    let is_regular_file = |path: &Path| path.metadata().is_ok_and(|m| m.is_file());
+    if is_regular_file(iso_path) {
         return Err(AppError::new(format!("{} already exists.", iso_path.display())));
     }
     if !root_path.exists() {
         return Err(AppError::new("Couldn't find root."));
     }
 
+    warn_about_dol(Layout::detect(root_path).find_dol(root_path));
+
     let iso = File::create(iso_path)?;
-    if let Err(_) = ROMRebuilder::rebuild(root_path, alignment, iso, rebuild_systemdata) {
-        remove_file(iso_path).unwrap();
-        Err(AppError::new("Couldn't rebuild iso."))
+    let result = ROMRebuilder::new(root_path, alignment, rebuild_systemdata)
+        .and_then(|mut rebuilder| {
+            rebuilder.set_trim(trim);
+            if ciso {
+                rebuilder.write_ciso(iso, DEFAULT_CISO_BLOCK_SIZE)
+            } else if iso.metadata()?.is_file() {
+                rebuilder.write_sparse(iso)
+            } else {
+                rebuilder.write(iso)
+            }
+        });
+    if let Err(e) = result {
+        if is_regular_file(iso_path) {
+            remove_file(iso_path).unwrap();
+        }
+        // Things like a file name that can't be encoded are something the
+        // user can fix
+        if e.kind() == io::ErrorKind::InvalidData || e.kind() == io::ErrorKind::InvalidInput {
+            Err(AppError::new(format!("Couldn't rebuild iso: {}", e)))
+        } else {
+            Err(AppError::new("Couldn't rebuild iso."))
+        }
     } else {
         Ok(())
     }
@@ -170,16 +574,18 @@ fn get_info(
     section_type: Option<&str>,
     offset: Option<&str>,
     mem_addr: Option<&str>,
+    map: Option<&str>,
+    tgc: Option<&str>,
     style: NumberStyle,
 ) -> AppResult {
     if let Some(offset) = offset {
-        find_offset(path.as_ref(), offset, style)
+        find_offset(path.as_ref(), offset, tgc, style)
     } else if let Some(addr) = mem_addr {
-        find_mem_addr(path.as_ref(), addr, style)
+        find_mem_addr(path.as_ref(), addr, map, tgc, style)
+    } else if section_type == Some("tgc") {
+        print_tgc_info(path.as_ref(), tgc, style)
     } else {
-        let mut f = File::open(path.as_ref())
-            .map(BufReader::new)
-            .map_err(|_| AppError::new("Couldn't open file"))?;
+        let mut f = open_rom(path.as_ref(), tgc)?;
         let game = Game::open(&mut f, 0);
         match section_type {
             Some("header") => {
@@ -199,7 +605,8 @@ fn get_info(
             Some("fst") => {
                 game
                     .map(|g| g.fst)
-                    .or_else(|_| FST::new(f, 0))
+                    // There's no header to get the encoding from
+                    .or_else(|_| FST::new(f, 0, TextEncoding::Windows1252))
                     .map_err(|_| AppError::new("Invalid iso or file system table"))?
                     .print_info(style);
             },
@@ -210,21 +617,127 @@ fn get_info(
                     .map_err(|_| AppError::new("Invalid iso or apploader"))?
                     .print_info(style);
             },
-            Some("layout") => { print_layout(path.as_ref())?; }
+            Some("layout") => { print_layout(path.as_ref(), tgc)?; }
             Some(_) => unreachable!(),
-            None => { print_iso_info(path.as_ref(), 0, style)? },
+            None => { print_iso_info(path.as_ref(), 0, tgc, style)? },
         }
         Ok(())
     }
 }
 
-fn print_layout(path: impl AsRef<Path>) -> AppResult {
-    let (game, _) = try_to_open_game(path.as_ref(), 0)?;
+fn print_layout(path: impl AsRef<Path>, tgc: Option<&str>) -> AppResult {
+    let (game, _) = try_to_open_game(path.as_ref(), 0, tgc)?;
     game.print_layout();
     Ok(())
 }
 
-fn find_offset(header_path: impl AsRef<Path>, offset: &str, style: NumberStyle) -> AppResult {
+fn print_rel_info(
+    path: impl AsRef<Path>,
+    rel_path: &str,
+    load_address: Option<&str>,
+    tgc: Option<&str>,
+    style: NumberStyle,
+) -> AppResult {
+    let load_address = match load_address {
+        Some(a) => Some(parse_address(a, "load address")?),
+        None => None,
+    };
+
+    let (game, mut iso) = try_to_open_game(path.as_ref(), 0, tgc)?;
+    let rel = read_rel(&game, &mut iso, rel_path)?;
+
+    rel.print_info_at(style, load_address);
+    Ok(())
+}
+
+fn read_rel(game: &Game, iso: &mut RomReader, rel_path: &str) -> Result<REL, AppError> {
+    let file = game.fst.entry_for_path(rel_path)
+        .and_then(|e| e.as_file())
+        .ok_or_else(|| AppError::new(format!("Couldn't find a file named {} in the ROM.", rel_path)))?;
+    REL::new(iso, file.file_offset, file.size)
+        .map_err(|e| AppError::new(format!("Couldn't read {}: {}", rel_path, e)))
+}
+
+fn parse_address(text: &str, name: &str) -> Result<u64, AppError> {
+    parse_as_u64(text)
+        .map_err(|_| AppError::new(format!("Invalid {}. Must be an integer.", name)))
+}
+
+fn link_rel(
+    rom_path: impl AsRef<Path>,
+    rel_path: &str,
+    output: impl AsRef<Path>,
+    base: &str,
+    bss: Option<&str>,
+    modules: Option<Values>,
+    elf: bool,
+) -> AppResult {
+    let output = output.as_ref();
+    if output.exists() {
+        return Err(AppError::new(format!("{} already exists.", output.display())));
+    }
+
+    let (game, mut iso) = try_to_open_game(rom_path, 0, None)?;
+    let rel = read_rel(&game, &mut iso, rel_path)?;
+    let mut addresses = rel.default_addresses(parse_address(base, "base address")?);
+    if let Some(bss) = bss {
+        addresses.bss_address = parse_address(bss, "BSS address")?;
+    }
+
+    let mut other_modules = Vec::new();
+    for module in modules.into_iter().flatten() {
+        let mut parts = module.rsplitn(2, '@');
+        let (address, path) = match (parts.next(), parts.next()) {
+            (Some(a), Some(p)) => (parse_address(a, "module address")?, p),
+            _ => return Err(AppError::new(format!("Invalid module {}, it should look like path@address.", module))),
+        };
+        let other = read_rel(&game, &mut iso, path)?;
+        let other_addresses = other.default_addresses(address);
+        other_modules.push((other, other_addresses));
+    }
+    let other_modules: Vec<_> = other_modules.iter().map(|(r, a)| (r, *a)).collect();
+
+    let mut image = rel.read_image(&mut iso)?;
+    let report = rel.link(&mut image, addresses, &other_modules)
+        .map_err(|e| AppError::new(format!("Couldn't link {}: {}", rel_path, e)))?;
+
+    let result = if elf {
+        rel.to_dol(&image, addresses)
+            .and_then(|dol| {
+                let mut file = io::BufWriter::new(File::create(output)?);
+                dol_to_elf(&dol, None, &mut file)?;
+                file.flush()
+            })
+    } else {
+        // The BSS is only included if it comes right after the rest of the REL
+        if addresses.bss_address == rel.default_addresses(addresses.load_address).bss_address {
+            let bss_size = rel.sections.iter().find(|s| s.is_bss()).map_or(0, |s| s.size);
+            image.resize((addresses.bss_address - addresses.load_address) as usize + bss_size, 0);
+        }
+        File::create(output).and_then(|mut f| f.write_all(&image))
+    };
+    if let Err(e) = result {
+        remove_file(output)?;
+        return Err(AppError::new(format!("Couldn't write {}: {}", output.display(), e)));
+    }
+
+    for s in rel.sections.iter().filter(|s| !s.is_empty()) {
+        let addr = rel.section_address(s.index, addresses.load_address, Some(addresses.bss_address)).unwrap();
+        println!("Section {:<2} {:#010x}-{:#010x}", s.index, addr, addr + s.size as u64);
+    }
+    println!("Applied {} relocations", report.relocations_applied);
+    for m in report.missing_modules {
+        println!("Warning: module {} wasn't given with --module, so its imports weren't linked", m);
+    }
+    Ok(())
+}
+
+fn find_offset(
+    header_path: impl AsRef<Path>,
+    offset: &str,
+    tgc: Option<&str>,
+    style: NumberStyle,
+) -> AppResult {
     let offset = parse_as_u64(offset).ok()
         .filter(|o| (*o as usize) < ROM_SIZE)
         .ok_or_else(|| AppError::new(format!(
@@ -232,20 +745,41 @@ fn find_offset(header_path: impl AsRef<Path>, offset: &str, style: NumberStyle)
             format_usize(ROM_SIZE, style),
         )))?;
 
-    let (game, _) = try_to_open_game(header_path.as_ref(), 0)?;
+    let (game, mut iso) = try_to_open_game(header_path.as_ref(), 0, tgc)?;
     let layout = game.rom_layout();
     let section = layout.find_offset(offset)
         .ok_or_else(|| AppError::new("There isn't any data at this offset."))?;
 
     section.print_info(style);
+
+    if let Some(rel) = game.rel_at_offset(&mut iso, offset) {
+        println!();
+        println!("REL module: {}", rel.module_id);
+        match rel.section_at_offset(offset) {
+            Some(s) => {
+                println!("REL section: {}", s.index);
+                println!(
+                    "Offset from start of section: {}",
+                    format_u64(offset - rel.offset - s.offset, style),
+                );
+            },
+            None => println!("REL section: none (this is part of the REL's header or relocations)"),
+        }
+    }
     Ok(())
 }
 
-fn find_mem_addr(path: impl AsRef<Path>, mem_addr: &str, style: NumberStyle) -> AppResult {
+fn find_mem_addr(
+    path: impl AsRef<Path>,
+    mem_addr: &str,
+    map: Option<&str>,
+    tgc: Option<&str>,
+    style: NumberStyle,
+) -> AppResult {
     let mem_addr = parse_as_u64(mem_addr)
         .map_err(|_| AppError::new("Invalid address. Must be an integer."))?;
 
-    let (game, _) = try_to_open_game(path.as_ref(), 0)?;
+    let (game, _) = try_to_open_game(path.as_ref(), 0, tgc)?;
 
     let seg = game.dol.segment_at_addr(mem_addr)
         .ok_or_else(|| AppError::new("No DOL segment will be loaded at this address."))?;
@@ -254,6 +788,293 @@ fn find_mem_addr(path: impl AsRef<Path>, mem_addr: &str, style: NumberStyle) ->
     println!("Segment: {}", seg.to_string());
     println!("Offset from start of segment: {}", format_u64(offset, style));
 
+    if let Some(map) = map {
+        let symbols = open_symbol_map(map)?;
+        match symbols.describe_addr(mem_addr) {
+            Some(s) => println!("Symbol: {}", s),
+            None => println!("Symbol: none"),
+        }
+    }
+
+    Ok(())
+}
+
+fn open_symbol_map(path: impl AsRef<Path>) -> Result<SymbolMap, AppError> {
+    SymbolMap::open(path.as_ref())
+        .map_err(|_| AppError::new(format!("Couldn't read the symbol map {}", path.as_ref().display())))
+}
+
+fn lookup_symbol(map: impl AsRef<Path>, query: &str) -> AppResult {
+    let symbols = open_symbol_map(map)?;
+
+    let symbol = match parse_as_u64(query) {
+        Ok(addr) => {
+            let s = symbols.symbol_at_addr(addr)
+                .ok_or_else(|| AppError::new(format!("No symbol contains {:#010x}.", addr)))?;
+            println!("{}", symbols.describe_addr(addr).unwrap());
+            s
+        },
+        Err(_) => symbols.find(query)
+            .ok_or_else(|| AppError::new(format!("There's no symbol named {}.", query)))?,
+    };
+
+    println!("Name: {}", symbol.name);
+    println!("Section: {}", symbol.section);
+    println!("Address: {:#010x}", symbol.address);
+    println!("Size: {:#x} bytes", symbol.size);
+    Ok(())
+}
+
+fn export_symbols(
+    map: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+    renames: Option<Values>,
+    removals: Option<Values>,
+) -> AppResult {
+    let output = output.as_ref();
+    if output.exists() {
+        return Err(AppError::new(format!("{} already exists.", output.display())));
+    }
+    let mut symbols = open_symbol_map(map)?;
+
+    for rename in renames.into_iter().flatten() {
+        let mut names = rename.splitn(2, '=');
+        let (old, new) = match (names.next(), names.next()) {
+            (Some(old), Some(new)) if !new.is_empty() => (old, new),
+            _ => return Err(AppError::new(format!("Invalid rename {}, it should look like old=new.", rename))),
+        };
+        if !symbols.rename(old, new) {
+            return Err(AppError::new(format!("There's no symbol named {}.", old)));
+        }
+    }
+    for name in removals.into_iter().flatten() {
+        symbols.remove(name)
+            .ok_or_else(|| AppError::new(format!("There's no symbol named {}.", name)))?;
+    }
+
+    let mut file = io::BufWriter::new(File::create(output)?);
+    symbols.write_dolphin(&mut file)?;
+    file.flush()?;
+    println!("Exported {} symbols", symbols.len());
+    Ok(())
+}
+
+fn parse_value_type(value_type: Option<&str>) -> ValueType {
+    // clap has already checked that it's one of the possible values
+    value_type.and_then(ValueType::parse).unwrap_or(ValueType::U32)
+}
+
+fn endianness(little_endian: bool) -> Endianness {
+    if little_endian {
+        Endianness::Little
+    } else {
+        Endianness::Big
+    }
+}
+
+// Returns whether `path` is a DOL file or an extracted ROM's root, rather than a ROM
+fn is_dol_or_root(path: &Path) -> bool {
+    path.is_dir() || path.extension().is_some_and(|e| e.eq_ignore_ascii_case("dol"))
+}
+
+fn dol_path(path: &Path) -> PathBuf {
+    if path.is_dir() {
+        Layout::detect(path).dol_path(path)
+    } else {
+        path.to_path_buf()
+    }
+}
+
+fn peek_memory(
+    path: impl AsRef<Path>,
+    mem_addr: &str,
+    count: Option<&str>,
+    value_type: Option<&str>,
+    little_endian: bool,
+    style: NumberStyle,
+) -> AppResult {
+    let path = path.as_ref();
+    let mem_addr = parse_as_u64(mem_addr)
+        .map_err(|_| AppError::new("Invalid address. Must be an integer."))?;
+    let value_type = parse_value_type(value_type);
+    let count = match count {
+        Some(c) => parse_as_usize(c)
+            .map_err(|_| AppError::new("Invalid count. Must be an integer."))?,
+        None if value_type == ValueType::String => DEFAULT_MAX_STRING_LEN,
+        None => 1,
+    };
+
+    let (dol, mut reader): (DOLHeader, RomReader) = if is_dol_or_root(path) {
+        let path = dol_path(path);
+        let file = File::open(&path)
+            .map_err(|_| AppError::new(format!("Couldn't open {}", path.display())))?;
+        let mut reader = BufReader::new(Box::new(file) as Box<dyn ReadSeek>);
+        let dol = DOLHeader::new(&mut reader, 0)
+            .map_err(|_| AppError::new("Invalid DOL"))?;
+        (dol, reader)
+    } else {
+        let (game, reader) = try_to_open_game(path, 0, None)?;
+        (game.dol, reader)
+    };
+
+    let values = memory::peek(&mut reader, &dol, mem_addr, value_type, count, endianness(little_endian))
+        .map_err(|e| AppError::new(format!("Couldn't read {:#010x}: {}", mem_addr, e)))?;
+
+    let size = value_type.size().unwrap_or(0) as u64;
+    for (i, v) in values.iter().enumerate() {
+        let value = match v {
+            Value::Integer(n) => format_u64(*n, style),
+            Value::Float(f) => f.to_string(),
+            Value::String(s) => format!("{:?}", String::from_utf8_lossy(s)),
+        };
+        println!("{:#010x}: {}", mem_addr + i as u64 * size, value);
+    }
+    Ok(())
+}
+
+fn poke_memory(
+    path: impl AsRef<Path>,
+    mem_addr: &str,
+    values: Values,
+    value_type: Option<&str>,
+    little_endian: bool,
+) -> AppResult {
+    let path = path.as_ref();
+    let mem_addr = parse_as_u64(mem_addr)
+        .map_err(|_| AppError::new("Invalid address. Must be an integer."))?;
+    let value_type = parse_value_type(value_type);
+
+    let mut bytes = Vec::new();
+    for v in values {
+        let encoded = value_type.encode(v, endianness(little_endian))
+            .ok_or_else(|| AppError::new(format!("{} isn't a valid {} value.", v, value_type.name())))?;
+        bytes.extend_from_slice(&encoded);
+    }
+
+    let file_path = dol_path(path);
+    let mut file = OpenOptions::new().read(true).write(true).open(&file_path)
+        .map_err(|_| AppError::new(format!("Couldn't open {}", file_path.display())))?;
+
+    let dol = if is_dol_or_root(path) {
+        DOLHeader::new(BufReader::new(&file), 0)
+            .map_err(|_| AppError::new("Invalid DOL"))?
+    } else {
+        // Compressed ROMs would have to be rebuilt to change them
+        if ContainerFormat::detect(&file)? != ContainerFormat::Iso {
+            return Err(AppError::new("Only plain ISOs can be changed in place."));
+        }
+        Game::open(BufReader::new(&file), 0)
+            .map_err(|_| AppError::new("Invalid iso."))?
+            .dol
+    };
+
+    let offset = memory::poke(&mut file, &dol, mem_addr, &bytes)
+        .map_err(|e| AppError::new(format!("Couldn't write to {:#010x}: {}", mem_addr, e)))?;
+    println!("Wrote {} bytes at offset {:#x}", bytes.len(), offset);
+    Ok(())
+}
+
+// Opens the header of a plain ISO, or of the root of an extracted ROM, to
+// be changed in place. There's no file for roots, since their header can be
+// split across more than one, so it's written with `save_header`.
+fn open_header(path: impl AsRef<Path>) -> Result<(Option<File>, Header), AppError> {
+    let path = path.as_ref();
+    if path.is_dir() {
+        let header = read_root_header(path)?;
+        return Ok((None, header));
+    }
+    let file = OpenOptions::new().read(true).write(true).open(path)
+        .map_err(|_| AppError::new(format!("Couldn't open {}", path.display())))?;
+    // Compressed ROMs would have to be rebuilt to change them
+    if ContainerFormat::detect(&file)? != ContainerFormat::Iso {
+        return Err(AppError::new("Only plain ISOs can be changed in place."));
+    }
+    let header = Header::new(BufReader::new(&file), 0)
+        .map_err(|_| AppError::new(format!("Invalid header: {}", path.display())))?;
+    Ok((Some(file), header))
+}
+
+fn save_header(path: impl AsRef<Path>, file: Option<File>, header: &Header) -> AppResult {
+    match file {
+        Some(mut file) => header.write_in_place(&mut file)?,
+        None => Layout::detect(path.as_ref()).write_header(path.as_ref(), header)?,
+    }
+    Ok(())
+}
+
+fn read_root_header(root: &Path) -> Result<Header, AppError> {
+    Layout::detect(root).read_header(root)
+        .map_err(|_| AppError::new(format!("Invalid header in {}", root.display())))
+}
+
+fn convert_region(path: impl AsRef<Path>, region: &str) -> AppResult {
+    let region = Region::parse(region)
+        .ok_or_else(|| AppError::new("Invalid region. Must be pal, ntsc-u, or ntsc-j."))?;
+    let (file, mut header) = open_header(&path)?;
+
+    let old_region = header.region();
+    header.set_region(region);
+    save_header(path, file, &header)?;
+    println!("Game ID: {}{}", header.game_code, header.maker_code);
+    println!("Region: {}", region.name());
+
+    if old_region.is_some_and(|r| (r == Region::Pal) != (region == Region::Pal)) {
+        eprintln!(
+            "Warning: the game's code still sets up the video mode it was made for. \
+            PAL consoles run at 50Hz and NTSC ones at 60Hz, so the game may run at \
+            the wrong speed or not display properly unless it's patched as well."
+        );
+    }
+    Ok(())
+}
+
+fn set_header(
+    path: impl AsRef<Path>,
+    title: Option<&str>,
+    game_id: Option<&str>,
+    maker: Option<&str>,
+    disc: Option<&str>,
+    version: Option<&str>,
+) -> AppResult {
+    if title.is_none() && game_id.is_none() && maker.is_none() && disc.is_none() && version.is_none() {
+        return Err(AppError::new("Nothing to change. Pass --title, --game-id, --maker, --disc, or --version."));
+    }
+    let disc = match disc {
+        Some(d) => match parse_as_u64(d) {
+            Ok(n @ 1..=256) => Some((n - 1) as u8),
+            _ => return Err(AppError::new("Invalid disc number. Must be between 1 and 256.")),
+        },
+        None => None,
+    };
+    let version = match version {
+        Some(v) => match parse_as_u64(v) {
+            Ok(n) if n <= 255 => Some(n as u8),
+            _ => return Err(AppError::new("Invalid version. Must be between 0 and 255.")),
+        },
+        None => None,
+    };
+
+    let (file, mut header) = open_header(&path)?;
+
+    let set = |result: io::Result<()>| result.map_err(|e| AppError::new(e.to_string()));
+    if let Some(title) = title {
+        set(header.set_title(title))?;
+    }
+    if let Some(game_id) = game_id {
+        set(header.set_game_id(game_id))?;
+    }
+    if let Some(maker) = maker {
+        set(header.set_maker_code(maker))?;
+    }
+    if let Some(disc) = disc {
+        header.disk_id = disc;
+    }
+    if let Some(version) = version {
+        header.version = version;
+    }
+
+    save_header(path, file, &header)?;
+    header.print_info(NumberStyle::Decimal);
     Ok(())
 }
 
@@ -261,8 +1082,9 @@ fn extract_section(
     iso_path: impl AsRef<Path>,
     section_filename: impl AsRef<Path>,
     output: impl AsRef<Path>,
+    tgc: Option<&str>,
 ) -> AppResult {
-    let (game, mut iso) = try_to_open_game(iso_path.as_ref(), 0)?;
+    let (game, mut iso) = try_to_open_game(iso_path.as_ref(), 0, tgc)?;
 
     let result = game.extract_section_with_name(
         section_filename,
@@ -277,22 +1099,510 @@ fn extract_section(
     }
 }
 
-fn ls_files(rom_path: impl AsRef<Path>, dir: Option<impl AsRef<Path>>, long_format: bool) -> AppResult {
-    let (game, _) = try_to_open_game(rom_path, 0)?;
+fn parse_address_range(range: Option<&str>) -> Result<Option<AddressRange>, AppError> {
+    match range {
+        Some(r) => Ok(Some(AddressRange::parse(r).ok_or_else(|| AppError::new(
+            "Invalid address range. Ranges look like 0x80003100..0x80003200",
+        ))?)),
+        None => Ok(None),
+    }
+}
+
+fn disassemble_dol(
+    rom_path: impl AsRef<Path>,
+    segment_names: Option<Values>,
+    range: Option<&str>,
+) -> AppResult {
+    let range = parse_address_range(range)?;
+
+    let (game, mut iso) = try_to_open_game(rom_path, 0, None)?;
+
+    let segments = match segment_names {
+        Some(names) => names
+            .map(|name| {
+                Segment::parse_segment_name(name)
+                    .and_then(|(t, n)| game.dol.find_segment(t, n))
+                    .ok_or_else(|| AppError::new(format!("Couldn't find a segment named {}.", name)))
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        None => game.dol.iter_segments()
+            .filter(|s| s.seg_type == SegmentType::Text)
+            .collect(),
+    };
+
+    let segments: Vec<_> = segments.into_iter()
+        .filter(|s| match range {
+            Some(r) => r.overlaps(s),
+            None => true,
+        })
+        .collect();
+    if segments.is_empty() {
+        return Err(AppError::new("No DOL segment will be loaded in this range."));
+    }
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for (i, s) in segments.iter().enumerate() {
+        if i > 0 { writeln!(out)?; }
+        disassemble_segment(&mut iso, s, range, &mut out)?;
+    }
+    Ok(())
+}
+
+// Reads the banner from a ROM, an extracted ROM's root, or a banner file
+fn open_banner(path: impl AsRef<Path>, tgc: Option<&str>) -> Result<Banner, AppError> {
+    let path = path.as_ref();
+    if path.is_dir() {
+        let header = read_root_header(path)?;
+        let banner_path = Layout::detect(path).files_path(path).join(BANNER_FILENAME);
+        return File::open(&banner_path)
+            .and_then(|f| Banner::new(f, 0, header.encoding()))
+            .map_err(|_| AppError::new(format!("Invalid banner: {}", banner_path.display())));
+    }
+
+    if tgc.is_none() {
+        // There's no header to get the encoding from
+        if let Ok(banner) = File::open(path).and_then(|f| Banner::new(f, 0, TextEncoding::Windows1252)) {
+            return Ok(banner);
+        }
+    }
+    let (game, mut iso) = try_to_open_game(path, 0, tgc)?;
+    game.read_banner(&mut iso)
+        .ok_or_else(|| AppError::new(format!("Couldn't find a valid {} in the ROM.", BANNER_FILENAME)))
+}
+
+fn show_banner(path: impl AsRef<Path>, png_path: Option<&str>, tgc: Option<&str>) -> AppResult {
+    let banner = open_banner(path, tgc)?;
+    banner.print_info(NumberStyle::Decimal);
+
+    if let Some(png_path) = png_path {
+        if Path::new(png_path).exists() {
+            return Err(AppError::new(format!("{} already exists.", png_path)));
+        }
+        banner.write_png(File::create(png_path)?)?;
+        println!();
+        println!("Saved the image to {}", png_path);
+    }
+    Ok(())
+}
+
+fn set_banner(
+    path: impl AsRef<Path>,
+    image: Option<&str>,
+    fields: Vec<(BannerField, &str)>,
+    language: Option<&str>,
+) -> AppResult {
+    if image.is_none() && fields.is_empty() {
+        return Err(AppError::new(
+            "Nothing to change. Pass --image, --name, --developer, --full-name, --full-developer, or --description.",
+        ));
+    }
+    let path = path.as_ref();
+
+    // Only called for files, since directories can't be opened everywhere
+    let is_banner_file = |path: &Path| -> io::Result<bool> {
+        Ok(!path.exists() ||
+            path.extension().is_some_and(|e| e.eq_ignore_ascii_case("bnr")) ||
+            Banner::new(File::open(path)?, 0, TextEncoding::Windows1252).is_ok())
+    };
+
+    // If there isn't a banner yet, PAL games get one with text in each language
+    let mut new_version = BannerVersion::Bnr1;
+
+    // Where the banner is, and where in that file. Banners in an ISO can't
+    // be made any bigger.
+    let (file_path, offset, max_size, encoding) = if path.is_dir() {
+        let header = read_root_header(path)?;
+        if header.region() == Some(Region::Pal) {
+            new_version = BannerVersion::Bnr2;
+        }
+        let banner_path = Layout::detect(path).files_path(path).join(BANNER_FILENAME);
+        (banner_path, 0, None, header.encoding())
+    } else if is_banner_file(path)? {
+        (path.to_path_buf(), 0, None, TextEncoding::Windows1252)
+    } else {
+        let file = File::open(path)?;
+        if ContainerFormat::detect(&file)? != ContainerFormat::Iso {
+            return Err(AppError::new("Only plain ISOs can be changed in place."));
+        }
+        let game = Game::open(BufReader::new(&file), 0)
+            .map_err(|_| AppError::new(format!("Invalid iso: {}.", path.display())))?;
+        let banner_file = game.banner_file()
+            .ok_or_else(|| AppError::new(format!("The ROM doesn't have an {}.", BANNER_FILENAME)))?;
+        (path.to_path_buf(), banner_file.file_offset, Some(banner_file.size), game.header.encoding())
+    };
+
+    let mut banner = match File::open(&file_path).and_then(|f| Banner::new(f, offset, encoding)) {
+        Ok(banner) => banner,
+        Err(ref e) if max_size.is_none() && e.kind() == io::ErrorKind::NotFound => Banner::blank(new_version),
+        Err(e) if max_size.is_none() => {
+            return Err(AppError::new(format!("Couldn't read {}: {}", file_path.display(), e)));
+        },
+        Err(_) => return Err(AppError::new(format!("The ROM's {} isn't a valid banner.", BANNER_FILENAME))),
+    };
+
+    let languages = match language {
+        Some(name) => {
+            let i = LANGUAGES.iter().position(|l| l.eq_ignore_ascii_case(name))
+                .ok_or_else(|| AppError::new(format!("Unknown language. Must be one of: {}.", LANGUAGES.join(", "))))?;
+            if i >= banner.metadata.len() {
+                return Err(AppError::new(format!("{} banners only have text in {}.", banner.version.name(), LANGUAGES[0])));
+            }
+            i..i + 1
+        },
+        None => 0..banner.metadata.len(),
+    };
+
+    if let Some(image) = image {
+        let (width, height, rgba) = File::open(image).and_then(decode_png)
+            .map_err(|e| AppError::new(format!("Couldn't read {}: {}", image, e)))?;
+        if width != BANNER_WIDTH || height != BANNER_HEIGHT {
+            return Err(AppError::new(format!(
+                "{} is {}x{}, but banners have to be {}x{}.",
+                image, width, height, BANNER_WIDTH, BANNER_HEIGHT,
+            )));
+        }
+        banner.set_rgba(&rgba)?;
+    }
+    for &(field, text) in &fields {
+        for metadata in &mut banner.metadata[languages.clone()] {
+            metadata.set(field, text, encoding)
+                .map_err(|e| AppError::new(e.to_string()))?;
+        }
+    }
+
+    let mut data = Vec::new();
+    banner.write(&mut data)?;
+    match max_size {
+        Some(size) if data.len() > size => {
+            return Err(AppError::new(format!(
+                "The new banner would be {} bytes, but there are only {} bytes for it in the ROM.",
+                data.len(),
+                size,
+            )));
+        },
+        Some(_) => {
+            let mut file = OpenOptions::new().write(true).open(&file_path)?;
+            file.seek(SeekFrom::Start(offset))?;
+            file.write_all(&data)?;
+        },
+        None => File::create(&file_path)?.write_all(&data)?,
+    }
+
+    banner.offset = offset;
+    banner.print_info(NumberStyle::Decimal);
+    Ok(())
+}
+
+fn build_memory_image(
+    rom_path: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+    tgc: Option<&str>,
+) -> AppResult {
+    let output = output.as_ref();
+    if output.exists() {
+        return Err(AppError::new(format!("{} already exists.", output.display())));
+    }
+
+    let (game, mut iso) = try_to_open_game(rom_path, 0, tgc)?;
+    let image = mem1_image(&mut iso, &game)
+        .map_err(|e| AppError::new(e.to_string()))?;
+    File::create(output)?.write_all(&image)?;
+    println!("Wrote a memory image starting at 0x80000000 to {}", output.display());
+    Ok(())
+}
+
+fn disassemble_apploader(rom_path: impl AsRef<Path>, range: Option<&str>) -> AppResult {
+    let range = parse_address_range(range)?;
+    let (game, mut iso) = try_to_open_game(rom_path, 0, None)?;
+    let code = game.apploader.read_code(&mut iso, APPLOADER_OFFSET)?;
+
+    let code_end = APPLOADER_LOAD_ADDRESS + code.len() as u64;
+    let (start, end) = match range {
+        Some(r) => (r.start.max(APPLOADER_LOAD_ADDRESS), r.end.min(code_end)),
+        None => (APPLOADER_LOAD_ADDRESS, code_end),
+    };
+    if start >= end {
+        return Err(AppError::new("The apploader's code isn't in this range."));
+    }
+    // Keep the instructions aligned
+    let start = start & !3;
+
+    let stdout = io::stdout();
+    let code = &code[(start - APPLOADER_LOAD_ADDRESS) as usize..(end - APPLOADER_LOAD_ADDRESS) as usize];
+    disassemble(code, start as u32, stdout.lock())?;
+    Ok(())
+}
+
+fn build_apploader(
+    output: impl AsRef<Path>,
+    code_path: impl AsRef<Path>,
+    entry_point: &str,
+    date: &str,
+    trailer_path: Option<&str>,
+) -> AppResult {
+    let output = output.as_ref();
+    if output.exists() {
+        return Err(AppError::new(format!("{} already exists.", output.display())));
+    }
+    let entry_point = parse_address(entry_point, "entry point")?;
+    let code = std::fs::read(code_path)?;
+    let trailer = match trailer_path {
+        Some(path) => std::fs::read(path)?,
+        None => Vec::new(),
+    };
+
+    // Check everything before creating the file
+    Apploader::write(date, entry_point, &code, &trailer, io::sink())
+        .map_err(|e| AppError::new(e.to_string()))?;
+    Apploader::write(date, entry_point, &code, &trailer, File::create(output)?)?;
+    println!("Wrote the apploader to {}", output.display());
+    Ok(())
+}
+
+fn print_tgc_info(path: impl AsRef<Path>, tgc: Option<&str>, style: NumberStyle) -> AppResult {
+    let header = match tgc {
+        Some(tgc_path) => {
+            let mut iso = open_rom(path.as_ref(), None)?;
+            let (offset, _) = find_tgc(&mut iso, tgc_path)?;
+            TGCHeader::new(&mut iso, offset)
+        },
+        None => File::open(path.as_ref()).and_then(|f| TGCHeader::new(f, 0)),
+    };
+    header.map_err(|_| AppError::new("Invalid TGC file"))?.print_info(style);
+    Ok(())
+}
+
+fn convert_tgc(
+    input: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+    tgc: Option<&str>,
+    trim: bool,
+) -> AppResult {
+    let input = input.as_ref();
+    let output = output.as_ref();
+
+    if output.exists() {
+        return Err(AppError::new(format!("{} already exists.", output.display())));
+    }
+    if tgc.is_none() && ContainerFormat::detect(File::open(input)?)? != ContainerFormat::Tgc {
+        return Err(AppError::new(format!("{} isn't a TGC file.", input.display())));
+    }
+
+    // Make sure there's actually a game in there before writing anything
+    let (_, mut iso) = try_to_open_game(input, 0, tgc)?;
+    iso.seek(SeekFrom::Start(0))?;
+
+    let mut file = File::create(output)?;
+    let result = io::copy(&mut iso, &mut file).and_then(|size| {
+        if !trim && size < ROM_SIZE as u64 {
+            // This leaves a hole rather than writing zeros, where supported
+            file.set_len(ROM_SIZE as u64)?;
+        }
+        Ok(())
+    });
+    if result.is_err() {
+        remove_file(output)?;
+        return Err(AppError::new("Couldn't convert the TGC file."));
+    }
+    Ok(())
+}
+
+fn patch_dol(
+    codes_path: impl AsRef<Path>,
+    dol_path: impl AsRef<Path>,
+    output: Option<&str>,
+    cave: Option<&str>,
+) -> AppResult {
+    let cave = match cave {
+        Some(c) => AddressRange::parse(c)
+            .ok_or_else(|| AppError::new("Invalid code cave, it should look like 0x80001800..0x80003000"))?,
+        None => DEFAULT_CAVE,
+    };
+
+    let mut dol_path = dol_path.as_ref().to_path_buf();
+    if dol_path.is_dir() {
+        dol_path = Layout::detect(&dol_path).dol_path(&dol_path);
+    }
+    let output = output.map_or(dol_path.clone(), |o| Path::new(o).to_path_buf());
+
+    let codes_file = File::open(codes_path.as_ref())
+        .map_err(|_| AppError::new(format!("Couldn't open {}", codes_path.as_ref().display())))?;
+    let codes = parse_codes(BufReader::new(codes_file))?;
+    if codes.is_empty() {
+        return Err(AppError::new("No codes were found."));
+    }
+
+    let dol_file = File::open(&dol_path)
+        .map_err(|_| AppError::new(format!("Couldn't open {}", dol_path.display())))?;
+    let mut dol = Dol::read(BufReader::new(dol_file), 0)
+        .map_err(|e| AppError::new(format!("Couldn't read {}: {}", dol_path.display(), e)))?;
+
+    let report = apply_codes(&mut dol, &codes, cave)
+        .map_err(|e| AppError::new(format!("Couldn't apply the codes: {}", e)))?;
+
+    for name in &report.applied {
+        println!("Applied {}", name);
+    }
+    for (name, reason) in &report.skipped {
+        println!("Skipped {}: {}", name, reason);
+    }
+    if let Some((start, end)) = report.cave {
+        println!("Inserted ASM at {:#010x}-{:#010x}", start, end);
+    }
+
+    if !report.applied.is_empty() {
+        let mut file = io::BufWriter::new(File::create(&output)?);
+        dol.write(&mut file)?;
+        file.flush()?;
+    }
+    Ok(())
+}
+
+// Prints any problems with the DOL that's about to be put in a ROM, since a
+// broken one won't boot. This only warns, the ROM is still rebuilt. ELF files
+// are checked after they're converted, like the rebuilder does.
+fn warn_about_dol(dol_path: impl AsRef<Path>) {
+    let dol = File::open(dol_path.as_ref()).and_then(|mut f| {
+        if Elf::is_elf(&mut f)? {
+            let mut converted = Vec::new();
+            elf_to_dol(BufReader::new(f))?.write(&mut converted)?;
+            DOLHeader::new(io::Cursor::new(converted), 0)
+        } else {
+            DOLHeader::new(BufReader::new(f), 0)
+        }
+    });
+    if let Ok(dol) = dol {
+        for d in dol.validate() {
+            eprintln!("Warning: {}: {}", dol_path.as_ref().display(), d);
+        }
+    }
+}
+
+fn convert_dol(
+    input: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+    map: Option<impl AsRef<Path>>,
+) -> AppResult {
+    let input = input.as_ref();
+    let output = output.as_ref();
+    if output.exists() {
+        return Err(AppError::new(format!("{} already exists.", output.display())));
+    }
+
+    let dol = if is_dol_or_root(input) {
+        let file = File::open(dol_path(input))
+            .map_err(|_| AppError::new("Couldn't open file"))?;
+        Dol::read(BufReader::new(file), 0)
+    } else {
+        let (game, mut iso) = try_to_open_game(input, 0, None)?;
+        Dol::read(&mut iso, game.dol.offset)
+    }.map_err(|e| AppError::new(format!("Couldn't read the DOL: {}", e)))?;
+
+    let symbols = match map {
+        Some(path) => Some(open_symbol_map(path)?),
+        None => None,
+    };
+
+    let result = File::create(output)
+        .and_then(|f| dol_to_elf(&dol, symbols.as_ref(), io::BufWriter::new(f)));
+    if let Err(e) = result {
+        remove_file(output)?;
+        return Err(AppError::new(format!("Couldn't write {}: {}", output.display(), e)));
+    }
+
+    if let Some(symbols) = symbols {
+        println!("Added {} symbols", symbols.len());
+    }
+    Ok(())
+}
+
+fn convert_elf(input: impl AsRef<Path>, output: impl AsRef<Path>) -> AppResult {
+    let output = output.as_ref();
+    if output.exists() {
+        return Err(AppError::new(format!("{} already exists.", output.display())));
+    }
+
+    let elf = File::open(input.as_ref())
+        .map_err(|_| AppError::new("Couldn't open file"))?;
+    let dol = elf_to_dol(BufReader::new(elf))
+        .map_err(|e| AppError::new(format!("Couldn't convert {}: {}", input.as_ref().display(), e)))?;
+
+    dol.write(File::create(output)?)?;
+
+    for s in dol.iter_segments() {
+        println!(
+            "{:<8} {:#010x}-{:#010x}",
+            s.name(),
+            s.loading_address,
+            s.end_address(),
+        );
+    }
+    if dol.bss_size > 0 {
+        println!(
+            "{:<8} {:#010x}-{:#010x}",
+            ".bss",
+            dol.bss_address,
+            dol.bss_address + dol.bss_size as u64,
+        );
+    }
+    println!("Entry point: {:#010x}", dol.entry_point);
+    Ok(())
+}
+
+fn ls_files(
+    rom_path: impl AsRef<Path>,
+    dir: Option<impl AsRef<Path>>,
+    long_format: bool,
+    tgc: Option<&str>,
+) -> AppResult {
+    let (game, mut iso) = try_to_open_game(rom_path, 0, tgc)?;
     let dir = match dir {
         Some(p) => game.fst.entry_for_path(p).and_then(|e| e.as_dir()),
         None => Some(game.fst.root()),
     };
 
     if let Some(d) = dir {
-        game.print_directory(d, long_format);
+        game.print_directory(&mut iso, d, long_format);
         Ok(())
     } else {
         Err(AppError::new("No directory with that name/path exists"))
     }
 }
 
-fn try_to_open_game<P>(path: P, offset: u64) -> Result<(Game, BufReader<File>), AppError>
+type RomReader = BufReader<Box<dyn ReadSeek>>;
+
+// Opens a ROM in any of the formats supported by `open_disc_image`. If `tgc`
+// is given, the TGC file at that path in the ROM is opened instead.
+fn open_rom(path: impl AsRef<Path>, tgc: Option<&str>) -> Result<RomReader, AppError> {
+    let file = File::open(path.as_ref())
+        .map_err(|_| AppError::new("Couldn't open file"))?;
+    let (_, reader) = open_disc_image(file)
+        .map_err(|e| AppError::new(format!("Couldn't read {}: {}", path.as_ref().display(), e)))?;
+    let mut reader = BufReader::new(reader);
+
+    match tgc {
+        Some(tgc_path) => {
+            let (offset, size) = find_tgc(&mut reader, tgc_path)?;
+            let tgc = TGCReader::new(reader.into_inner(), offset, size)
+                .map_err(|e| AppError::new(format!("Couldn't read {}: {}", tgc_path, e)))?;
+            Ok(BufReader::new(Box::new(tgc)))
+        },
+        None => Ok(reader),
+    }
+}
+
+// Finds the offset and size of the TGC file at `tgc_path` in a ROM.
+fn find_tgc(iso: &mut RomReader, tgc_path: &str) -> Result<(u64, u64), AppError> {
+    let game = Game::open(&mut *iso, 0)
+        .map_err(|_| AppError::new("Invalid iso."))?;
+    game.fst.entry_for_path(tgc_path)
+        .and_then(|e| e.as_file())
+        .map(|f| (f.file_offset, f.size as u64))
+        .ok_or_else(|| AppError::new(format!("Couldn't find a file named {} in the ROM.", tgc_path)))
+}
+
+fn try_to_open_game<P>(path: P, offset: u64, tgc: Option<&str>) -> Result<(Game, RomReader), AppError>
 where
     P: AsRef<Path>,
 {
@@ -301,8 +1611,7 @@ where
         return Err(AppError::new(format!("The iso {} doesn't exist.", path.display())));
     }
 
-    let iso = File::open(path).expect("Couldn't open file");
-    let mut iso = BufReader::new(iso);
+    let mut iso = open_rom(path, tgc)?;
     Game::open(&mut iso, offset)
         .map(|game| (game, iso))
         .map_err(|_| AppError::new(format!("Invalid iso: {}.", path.display())))
@@ -0,0 +1,295 @@
+// Reading and writing typed values at the virtual addresses the main DOL is
+// loaded at, for making quick tweaks to a game's data. This also builds
+// images of main memory (MEM1) as it would be once the game is booted.
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+use ::{Game, parse_as_u64};
+use sections::dol::{DOLHeader, MEMORY_END, MEMORY_START};
+use sections::header::Region;
+
+pub const MEM1_SIZE: usize = (MEMORY_END - MEMORY_START) as usize;
+
+// The parts of the OS globals at the start of memory that are filled
+// in before the game starts. See section 4.2.1 of yagcd:
+// http://hitmen.c02.at/files/yagcd/yagcd/chap4.html
+const DISK_ID_SIZE: usize = 0x20;
+const BOOT_MAGIC_ADDR: usize = 0x20;
+const BOOT_MAGIC: u32 = 0x0D15_EA5E;
+const BOOT_VERSION_ADDR: usize = 0x24;
+const MEMORY_SIZE_ADDR: usize = 0x28;
+const CONSOLE_TYPE_ADDR: usize = 0x2C;
+// What Dolphin uses for a retail console
+const CONSOLE_TYPE_RETAIL: u32 = 3;
+const ARENA_HIGH_ADDR: usize = 0x34;
+const FST_ADDR_ADDR: usize = 0x38;
+const FST_MAX_SIZE_ADDR: usize = 0x3C;
+const TV_MODE_ADDR: usize = 0xCC;
+const ARAM_SIZE_ADDR: usize = 0xD0;
+const ARAM_SIZE: u32 = 0x0100_0000;
+const DEBUG_MONITOR_SIZE_ADDR: usize = 0xE8;
+const DEBUG_MONITOR_ADDR_ADDR: usize = 0xEC;
+const SIMULATED_MEMORY_SIZE_ADDR: usize = 0xF0;
+const BUS_CLOCK_ADDR: usize = 0xF8;
+const BUS_CLOCK: u32 = 162_000_000;
+const CPU_CLOCK_ADDR: usize = 0xFC;
+const CPU_CLOCK: u32 = 486_000_000;
+
+const TV_MODE_PAL: u32 = 1;
+
+// Strings are read until a null byte, or until this many bytes if no
+// maximum length is given
+pub const DEFAULT_MAX_STRING_LEN: usize = 256;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Endianness {
+    Big,
+    Little,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ValueType {
+    U8,
+    U16,
+    U32,
+    F32,
+    String,
+}
+
+impl ValueType {
+    pub fn parse(name: &str) -> Option<ValueType> {
+        use self::ValueType::*;
+        match name.to_lowercase().as_str() {
+            "u8" => Some(U8),
+            "u16" => Some(U16),
+            "u32" => Some(U32),
+            "f32" => Some(F32),
+            "string" => Some(String),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        use self::ValueType::*;
+        match self {
+            U8 => "u8",
+            U16 => "u16",
+            U32 => "u32",
+            F32 => "f32",
+            String => "string",
+        }
+    }
+
+    // The size of one value in bytes, or `None` for strings
+    pub fn size(self) -> Option<usize> {
+        use self::ValueType::*;
+        match self {
+            U8 => Some(1),
+            U16 => Some(2),
+            U32 | F32 => Some(4),
+            String => None,
+        }
+    }
+
+    // Converts the text of a value into bytes. Strings get a null byte added
+    // to the end. Integers can be in hex (with 0x) and can be negative.
+    pub fn encode(self, text: &str, endianness: Endianness) -> Option<Vec<u8>> {
+        use self::ValueType::*;
+
+        let size = match self {
+            String => {
+                let mut bytes = text.as_bytes().to_vec();
+                bytes.push(0);
+                return Some(bytes);
+            },
+            _ => self.size().unwrap(),
+        };
+        let bits = size as u32 * 8;
+        let n = if self == F32 {
+            text.parse::<f32>().ok()?.to_bits() as u64
+        } else if let Some(text) = text.strip_prefix('-') {
+            let n = parse_as_u64(text).ok()?;
+            if n > 1 << (bits - 1) {
+                return None;
+            }
+            n.wrapping_neg() & (u64::MAX >> (64 - bits))
+        } else {
+            let n = parse_as_u64(text).ok()?;
+            if n >> bits != 0 {
+                return None;
+            }
+            n
+        };
+
+        let mut bytes = vec![0; size];
+        match endianness {
+            Endianness::Big => BigEndian::write_uint(&mut bytes, n, size),
+            Endianness::Little => LittleEndian::write_uint(&mut bytes, n, size),
+        }
+        Some(bytes)
+    }
+
+    // Reads a single integer or float from the start of `bytes`
+    fn decode(self, bytes: &[u8], endianness: Endianness) -> Value {
+        let size = self.size().unwrap();
+        let n = match endianness {
+            Endianness::Big => BigEndian::read_uint(bytes, size),
+            Endianness::Little => LittleEndian::read_uint(bytes, size),
+        };
+        match self {
+            ValueType::F32 => Value::Float(f32::from_bits(n as u32)),
+            _ => Value::Integer(n),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Integer(u64),
+    Float(f32),
+    String(Vec<u8>),
+}
+
+// Finds the offset of `size` bytes at `mem_addr`, which must all be in the
+// same segment of the DOL.
+pub fn offset_of_addr(dol: &DOLHeader, mem_addr: u64, size: usize) -> io::Result<u64> {
+    let seg = dol.segment_at_addr(mem_addr)
+        .ok_or_else(|| io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("No DOL segment will be loaded at {:#010x}", mem_addr),
+        ))?;
+    if mem_addr + size as u64 > seg.loading_address + seg.size as u64 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{:#010x}-{:#010x} goes past the end of {}", mem_addr, mem_addr + size as u64, seg.to_string()),
+        ));
+    }
+    Ok(seg.offset + (mem_addr - seg.loading_address))
+}
+
+// Reads `count` values at `mem_addr`. For strings, `count` is the maximum
+// length of the string instead.
+pub fn peek<R>(
+    mut reader: R,
+    dol: &DOLHeader,
+    mem_addr: u64,
+    value_type: ValueType,
+    count: usize,
+    endianness: Endianness,
+) -> io::Result<Vec<Value>>
+where
+    R: Read + Seek,
+{
+    let size = match value_type.size() {
+        Some(s) => s * count,
+        // Don't read past the end of the segment looking for the end of the string
+        None => dol.segment_at_addr(mem_addr)
+            .map(|s| (s.loading_address + s.size as u64 - mem_addr) as usize)
+            .unwrap_or(count)
+            .min(count),
+    };
+    let offset = offset_of_addr(dol, mem_addr, size)?;
+
+    let mut bytes = vec![0; size];
+    reader.seek(SeekFrom::Start(offset))?;
+    reader.read_exact(&mut bytes)?;
+
+    Ok(match value_type.size() {
+        Some(s) => bytes.chunks(s).map(|b| value_type.decode(b, endianness)).collect(),
+        None => {
+            let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+            bytes.truncate(len);
+            vec![Value::String(bytes)]
+        },
+    })
+}
+
+// Writes `bytes` to the DOL at `mem_addr`, and returns the offset they
+// were written to.
+pub fn poke<W>(mut writer: W, dol: &DOLHeader, mem_addr: u64, bytes: &[u8]) -> io::Result<u64>
+where
+    W: Write + Seek,
+{
+    let offset = offset_of_addr(dol, mem_addr, bytes.len())?;
+    writer.seek(SeekFrom::Start(offset))?;
+    writer.write_all(bytes)?;
+    Ok(offset)
+}
+
+// Builds an image of MEM1 as it would be right before the game's entry point
+// is called: the DOL's segments are put where they're loaded, the BSS is
+// zeros, and the FST is at the top of memory. The OS globals in low memory
+// that the IPL and apploader would set are filled in from the header.
+pub fn mem1_image<R>(mut iso: R, game: &Game) -> io::Result<Vec<u8>>
+where
+    R: Read + Seek,
+{
+    let mut image = vec![0; MEM1_SIZE];
+    let header = &game.header;
+    let info = &header.information;
+
+    let fst_addr = (MEMORY_END - header.max_fst_size as u64) & !0x1F;
+    let fst_end = fst_addr + header.fst_size as u64;
+    if header.fst_size > header.max_fst_size || fst_addr < MEMORY_START {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "The FST doesn't fit in memory"));
+    }
+
+    iso.seek(SeekFrom::Start(0))?;
+    iso.read_exact(&mut image[..DISK_ID_SIZE])?;
+    let simulated_memory_size = match info.simulated_memory_size {
+        0 => MEM1_SIZE as u32,
+        size => size,
+    };
+    let tv_mode = if header.region() == Some(Region::Pal) { TV_MODE_PAL } else { 0 };
+    let globals = [
+        (BOOT_MAGIC_ADDR, BOOT_MAGIC),
+        (BOOT_VERSION_ADDR, 1),
+        (MEMORY_SIZE_ADDR, MEM1_SIZE as u32),
+        (CONSOLE_TYPE_ADDR, CONSOLE_TYPE_RETAIL),
+        (ARENA_HIGH_ADDR, fst_addr as u32),
+        (FST_ADDR_ADDR, fst_addr as u32),
+        (FST_MAX_SIZE_ADDR, header.max_fst_size as u32),
+        (TV_MODE_ADDR, tv_mode),
+        (ARAM_SIZE_ADDR, ARAM_SIZE),
+        (DEBUG_MONITOR_SIZE_ADDR, info.debug_monitor_size),
+        (DEBUG_MONITOR_ADDR_ADDR, header.debug_monitor_load_addr),
+        (SIMULATED_MEMORY_SIZE_ADDR, simulated_memory_size),
+        (BUS_CLOCK_ADDR, BUS_CLOCK),
+        (CPU_CLOCK_ADDR, CPU_CLOCK),
+    ];
+    for &(addr, value) in &globals {
+        BigEndian::write_u32(&mut image[addr..addr + 4], value);
+    }
+
+    let image_range = |start: u64, end: u64, name: &str| {
+        if start < MEMORY_START || end > MEMORY_END || start > end {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} at {:#010x}-{:#010x} isn't in MEM1", name, start, end),
+            ))
+        } else {
+            Ok((start - MEMORY_START) as usize..(end - MEMORY_START) as usize)
+        }
+    };
+
+    // The image starts out as zeros, so the BSS is already cleared. This
+    // only checks that it fits in MEM1.
+    let dol = &game.dol;
+    if dol.bss_size != 0 {
+        image_range(dol.bss_address, dol.bss_address + dol.bss_size as u64, "The BSS")?;
+    }
+
+    for s in dol.iter_segments() {
+        let range = image_range(s.loading_address, s.loading_address + s.size as u64, &s.to_string())?;
+        iso.seek(SeekFrom::Start(s.offset))?;
+        iso.read_exact(&mut image[range])?;
+    }
+
+    let range = image_range(fst_addr, fst_end, "The FST")?;
+    iso.seek(SeekFrom::Start(header.fst_offset))?;
+    iso.read_exact(&mut image[range])?;
+
+    Ok(image)
+}
@@ -1,35 +1,126 @@
 use std::cmp;
-use std::fs::{File, read_dir};
-use std::io::{self, BufReader, Write};
+use std::collections::HashMap;
+use std::fs::{self, File, read_dir};
+use std::io::{self, BufReader, Seek, SeekFrom, Write};
 use std::path::{self, Path, PathBuf};
 use std::sync::Mutex;
 
+use tempfile::NamedTempFile;
+
+use compression::yaz0;
+use container::ciso::CisoWriter;
+use elf::{elf_to_dol, Elf};
+use layout::Layout;
 use sections::apploader::APPLOADER_OFFSET;
 use sections::fst::{
     FST,
     entry::{DirectoryEntry, Entry, EntryInfo, FileEntry},
 };
 use sections::header::Header;
+use text::TextEncoding;
 use ::{
     align,
     DEFAULT_ALIGNMENT,
     extract_section,
-    paths::*,
     WRITE_CHUNK_SIZE,
 };
 
 pub const ROM_SIZE: usize = 0x57058000;
 
 // TODO: modify the config struct to include stuff like whether the system data should be rebuilt
-// and the paths for stuff like the dol, apploader, fst, and so on...
 
 // Header -> apploader -> fst -> dol -> fs
 
 struct ROMConfig<'a> {
     alignment: u64,
     root_path: &'a Path,
+    // Where the system data is in `root_path`, and where the files are
+    layout: Layout,
+    files_path: PathBuf,
+    // This is usually just the layout's DOL in the root, but if that's an ELF
+    // file, it's a temporary file with the converted DOL.
+    dol_path: PathBuf,
+    // Whether `dol_path` was converted from an ELF file
+    dol_from_elf: bool,
+    // The files in the Yaz0 list, and the temporary files they were
+    // compressed to, which are put in the ROM instead
+    compressed_files: HashMap<PathBuf, PathBuf>,
     files: Vec<(u64, PathBuf)>,
     space_used: Option<usize>,
+    // Kept around so they aren't deleted until the ROM is written
+    temp_files: Vec<NamedTempFile>,
+}
+
+impl<'a> ROMConfig<'a> {
+    fn new(root_path: &'a Path, alignment: u64) -> io::Result<ROMConfig<'a>> {
+        let mut temp_files = Vec::new();
+        let layout = Layout::detect(root_path);
+
+        let mut dol_path = layout.find_dol(root_path);
+        let mut dol_file = File::open(&dol_path)?;
+        let dol_from_elf = Elf::is_elf(&mut dol_file)?;
+        if dol_from_elf {
+            let dol = elf_to_dol(BufReader::new(dol_file))?;
+            let mut temp = NamedTempFile::new()?;
+            dol.write(&mut temp)?;
+            dol_path = temp.path().to_path_buf();
+            temp_files.push(temp);
+        }
+
+        let files_path = layout.files_path(root_path);
+        let compressed_files = ROMConfig::compress_listed_files(
+            &layout.yaz0_list_path(root_path),
+            &files_path,
+            &mut temp_files,
+        )?;
+
+        Ok(ROMConfig {
+            alignment,
+            root_path,
+            layout,
+            files_path,
+            dol_path,
+            dol_from_elf,
+            compressed_files,
+            files: vec![],
+            space_used: None,
+            temp_files,
+        })
+    }
+
+    fn compress_listed_files(
+        list_path: &Path,
+        files_path: &Path,
+        temp_files: &mut Vec<NamedTempFile>,
+    ) -> io::Result<HashMap<PathBuf, PathBuf>> {
+        let mut compressed_files = HashMap::new();
+        if !list_path.exists() {
+            return Ok(compressed_files);
+        }
+
+        let list = fs::read_to_string(list_path)?;
+        for name in list.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let path = files_path.join(name);
+            // Files that have been deleted since just aren't in the ROM
+            if !path.is_file() { continue }
+
+            let mut temp = NamedTempFile::new()?;
+            temp.write_all(&yaz0::compress(&fs::read(&path)?))?;
+            compressed_files.insert(path, temp.path().to_path_buf());
+            temp_files.push(temp);
+            print!("\r{} files compressed.", compressed_files.len());
+        }
+        if !compressed_files.is_empty() {
+            println!();
+        }
+        Ok(compressed_files)
+    }
+
+    // The file that's put in the ROM for `path`, which is different if it's
+    // compressed
+    fn rom_file_path<'b>(&'b self, path: &'b Path) -> &'b Path {
+        self.compressed_files.get(path).map_or(path, PathBuf::as_path)
+    }
 }
 
 struct FSTRebuilderInfo {
@@ -55,6 +146,8 @@ impl FSTRebuilderInfo {
 struct FSTRebuilder<'a> {
     apploader_size: usize,
     dol_size: usize,
+    // What the file names are encoded in, from the header's region
+    encoding: TextEncoding,
     config: ROMConfig<'a>,
 }
 
@@ -63,21 +156,19 @@ impl<'a> FSTRebuilder<'a> {
     where
         P: AsRef<Path>,
     {
-        let apploader = File::open(root.as_ref().join(APPLOADER_PATH))?;
+        let config = ROMConfig::new(root.as_ref(), alignment)?;
+
+        let apploader = File::open(config.layout.apploader_path(root))?;
         let apploader_size = apploader.metadata()?.len() as usize;
 
-        let dol = File::open(root.as_ref().join(DOL_PATH))?;
-        let dol_size = dol.metadata()?.len() as usize;
+        let encoding = config.layout.read_header(root)?.encoding();
+        let dol_size = config.dol_path.metadata()?.len() as usize;
 
         Ok(FSTRebuilder {
             apploader_size,
             dol_size,
-            config: ROMConfig {
-                alignment,
-                root_path: root.as_ref(),
-                files: vec![],
-                space_used: None,
-            },
+            encoding,
+            config,
         })
     }
 
@@ -86,6 +177,7 @@ impl<'a> FSTRebuilder<'a> {
             info: EntryInfo {
                 index: 0,
                 name: path::MAIN_SEPARATOR.to_string(),
+                raw_name: Vec::new(),
                 filename_offset: 0,
                 directory_index: None,
                 full_path: "/".into(),
@@ -104,7 +196,7 @@ impl<'a> FSTRebuilder<'a> {
             alignment: self.config.alignment,
         };
 
-        self.rebuild_dir_info(self.config.root_path, root_entry, &mut rb_info)?;
+        self.rebuild_dir_info(&self.config.files_path, root_entry, &mut rb_info)?;
 
         let size = rb_info.entries.len() * 12 + rb_info.filename_offset as usize;
         let offset = align(APPLOADER_OFFSET + self.apploader_size as u64, self.config.alignment);
@@ -128,7 +220,7 @@ impl<'a> FSTRebuilder<'a> {
             total_file_system_size: rb_info.file_system_size as usize,
             size,
         };
-        let fst_path = self.config.root_path.join(FST_PATH);
+        let fst_path = self.config.layout.fst_path(self.config.root_path);
         fst.write(File::create(&fst_path)?)?;
 
         self.config.space_used = Some(max_eof);
@@ -182,16 +274,22 @@ impl<'a> FSTRebuilder<'a> {
                 continue
             }
 
+            let raw_name = self.encoding.encode(&filename).ok_or_else(|| io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("The name of {} can't be written in {}", e.path().display(), self.encoding.name()),
+            ))?;
+
             let index = rb_info.entries.len() as usize;
             let info = EntryInfo {
                 index,
                 name: filename.clone().into_owned(),
+                raw_name,
                 filename_offset: rb_info.filename_offset,
                 directory_index: rb_info.parent_index,
                 full_path: rb_info.current_path.join(&*filename),
             };
             // plus 1 for the null byte
-            rb_info.filename_offset += info.name.chars().count() as u64 + 1;
+            rb_info.filename_offset += info.raw_name.len() as u64 + 1;
 
             if e.file_type()?.is_dir() {
                 let parent_index = info.directory_index.unwrap_or(0);
@@ -208,7 +306,7 @@ impl<'a> FSTRebuilder<'a> {
                 let entry = Entry::File(FileEntry {
                     info,
                     file_offset: rb_info.file_system_size,
-                    size: e.metadata()?.len() as usize,
+                    size: self.config.rom_file_path(&e.path()).metadata()?.len() as usize,
                 });
                 rb_info.add_entry(entry);
             }
@@ -230,9 +328,8 @@ struct HeaderRebuilder<'a> {
 
 impl<'a> HeaderRebuilder<'a> {
    fn rebuild(self) -> io::Result<FileSystemRebuilder<'a>> {
-        let header_path = self.config.root_path.join(HEADER_PATH);
-        let header_buf = BufReader::new(File::open(&header_path)?);
-        let mut header = Header::new(header_buf, 0)?;
+        let layout = self.config.layout;
+        let mut header = layout.read_header(self.config.root_path)?;
 
         header.dol_offset = self.dol_offset as u64;
         header.fst_offset = self.fst.offset as u64;
@@ -241,7 +338,7 @@ impl<'a> HeaderRebuilder<'a> {
         // TODO: Is this okay to assume?
         header.max_fst_size = self.fst.size;
 
-        header.write(File::create(&header_path)?)?;
+        layout.write_header(self.config.root_path, &header)?;
 
         Ok(FileSystemRebuilder {
             fst: self.fst,
@@ -259,23 +356,31 @@ struct FileSystemRebuilder<'a> {
 
 impl<'a> FileSystemRebuilder<'a> {
     fn rebuild(mut self) -> io::Result<ROMRebuilder> {
-        let apploader_path = self.config.root_path.join(APPLOADER_PATH);
-        let dol_path = self.config.root_path.join(DOL_PATH);
-        let fst_path = self.config.root_path.join(FST_PATH);
-        let header_path = self.config.root_path.join(HEADER_PATH);
+        let layout = self.config.layout;
+        let root_path = self.config.root_path;
+        let apploader_path = layout.apploader_path(root_path);
+        let dol_path = self.config.dol_path.clone();
+        let fst_path = layout.fst_path(root_path);
 
         self.config.files.push((APPLOADER_OFFSET, apploader_path));
         self.config.files.push((self.header.dol_offset, dol_path));
         self.config.files.push((self.fst.offset, fst_path));
-        self.config.files.push((0, header_path));
+        self.config.files.extend(layout.header_files(root_path));
 
-        FileSystemRebuilder::fill_files(&mut self.config.files, self.fst.entries[0].as_dir().unwrap(), self.config.root_path, &self.fst);
+        FileSystemRebuilder::fill_files(&mut self.config.files, self.fst.entries[0].as_dir().unwrap(), &self.config.files_path, &self.fst);
+        for file in &mut self.config.files {
+            if let Some(compressed) = self.config.compressed_files.get(&file.1) {
+                file.1 = compressed.clone();
+            }
+        }
 
         self.config.files.sort();
 
         Ok(ROMRebuilder {
             files: self.config.files,
             space_used: self.config.space_used,
+            trim: false,
+            _temp_files: self.config.temp_files,
         })
     }
 
@@ -311,42 +416,88 @@ impl<'a> FileSystemRebuilder<'a> {
 pub struct ROMRebuilder {
     files: Vec<(u64, PathBuf)>,
     space_used: Option<usize>,
+    trim: bool,
+    _temp_files: Vec<NamedTempFile>,
 }
 
 impl ROMRebuilder {
-    pub fn rebuild(root: impl AsRef<Path>, alignment: u64, output: impl Write, rebuild_systemdata: bool) -> io::Result<()> {
+    // Lays out the files in `root`, without writing the ROM yet.
+    pub fn new(root: impl AsRef<Path>, alignment: u64, rebuild_systemdata: bool) -> io::Result<ROMRebuilder> {
         let root = root.as_ref();
         if rebuild_systemdata {
             FSTRebuilder::new(root, alignment)?
                 .rebuild()?
                 .rebuild()?
-                .rebuild()?
-                .write(output)
+                .rebuild()
         } else {
-            let fst_file = File::open(root.join(FST_PATH))?;
-            let header_file = File::open(root.join(HEADER_PATH))?;
+            let layout = Layout::detect(root);
+            // Compressing a file can change its size, and the existing FST
+            // only has room for the sizes the files were extracted with
+            let yaz0_list_path = layout.yaz0_list_path(root);
+            if yaz0_list_path.exists() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} lists files to compress, so the FST has to be rebuilt", yaz0_list_path.display()),
+                ));
+            }
+            let fst_file = File::open(layout.fst_path(root))?;
 
-            let mut fst = FST::new(BufReader::new(fst_file), 0)?;
-            let header = Header::new(BufReader::new(header_file), 0)?;
+            let header = layout.read_header(root)?;
+            let mut fst = FST::new(BufReader::new(fst_file), 0, header.encoding())?;
             fst.offset = header.fst_offset;
 
+            // The converted DOL is very unlikely to be the same size as the
+            // one the header's offsets were made for
+            let config = ROMConfig::new(root, alignment)?;
+            if config.dol_from_elf {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "The DOL has to be converted from an ELF file, so the FST has to be rebuilt",
+                ));
+            }
+
             FileSystemRebuilder {
                 fst,
                 header,
-                config: ROMConfig {
-                    alignment,
-                    root_path: root,
-                    files: vec![],
-                    space_used: None,
-                }
-            }.rebuild()?.write(output)
+                config,
+            }.rebuild()
         }
     }
 
-    fn write(
-        &self,
-        mut output: impl Write,
-    ) -> io::Result<()> {
+    pub fn rebuild(root: impl AsRef<Path>, alignment: u64, output: impl Write, rebuild_systemdata: bool) -> io::Result<()> {
+        ROMRebuilder::new(root, alignment, rebuild_systemdata)?.write(output)
+    }
+
+    // If `trim` is true, the ROM ends right after the last file instead of
+    // being padded to `ROM_SIZE`. Nothing in the header or FST refers to
+    // the padding, so trimmed ROMs still work in Dolphin and Nintendont.
+    pub fn set_trim(&mut self, trim: bool) {
+        self.trim = trim;
+    }
+
+    // Writes every byte of the ROM, including the padding between files.
+    // This works with any writer, like a pipe.
+    pub fn write(&self, output: impl Write) -> io::Result<()> {
+        self.write_to(&mut ZeroFillWriter(output))
+    }
+
+    // Seeks over the padding instead of writing it, so on most file
+    // systems the gaps become holes and don't take up any space.
+    pub fn write_sparse(&self, output: impl Write + Seek) -> io::Result<()> {
+        let mut writer = SparseWriter::new(output);
+        self.write_to(&mut writer)?;
+        writer.finish()
+    }
+
+    // Writes the ROM as a CISO file. Blocks that would only contain
+    // padding are left out.
+    pub fn write_ciso(&self, output: impl Write + Seek, block_size: u32) -> io::Result<()> {
+        let mut writer = CisoWriter::new(output, block_size)?;
+        self.write_to(&mut writer)?;
+        writer.finish().map(|_| ())
+    }
+
+    fn write_to(&self, output: &mut impl RomWriter) -> io::Result<()> {
         let mut bytes_written = 0;
         let total_files = self.files.len();
 
@@ -356,10 +507,10 @@ impl ROMRebuilder {
 
             if size == 0 { continue }
 
-            write_zeros((offset - bytes_written) as usize, &mut output)?;
+            output.skip(offset - bytes_written)?;
             bytes_written = offset;
 
-            extract_section(&mut file, size as usize, &mut output)?;
+            extract_section(&mut file, size as usize, &mut *output)?;
             bytes_written += size;
 
             if bytes_written as usize > ROM_SIZE {
@@ -375,7 +526,9 @@ impl ROMRebuilder {
             print!("\r{}/{} files added.", i + 1, total_files);
         }
         println!();
-        write_zeros(ROM_SIZE - bytes_written as usize, &mut output)?;
+        if !self.trim {
+            output.skip(ROM_SIZE as u64 - bytes_written)?;
+        }
 
         if let Some(space) = self.space_used {
             let percent_used = ((space as f64 / ROM_SIZE as f64) * 100.0) as usize;
@@ -386,6 +539,88 @@ impl ROMRebuilder {
     }
 }
 
+// The gaps between files in a ROM are handled differently depending on
+// what kind of file is being written, so they're passed to `skip`.
+trait RomWriter: Write {
+    fn skip(&mut self, count: u64) -> io::Result<()>;
+}
+
+// Fills gaps with zeros, for writers that can't skip over them.
+struct ZeroFillWriter<W>(W);
+
+impl<W: Write> Write for ZeroFillWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl<W: Write> RomWriter for ZeroFillWriter<W> {
+    fn skip(&mut self, count: u64) -> io::Result<()> {
+        write_zeros(count as usize, &mut self.0)
+    }
+}
+
+// Seeks over gaps instead of writing them.
+struct SparseWriter<W> {
+    writer: W,
+    // Bytes that have been skipped but not seeked over yet
+    pending: u64,
+}
+
+impl<W: Write + Seek> SparseWriter<W> {
+    fn new(writer: W) -> SparseWriter<W> {
+        SparseWriter { writer, pending: 0 }
+    }
+
+    fn seek_pending(&mut self) -> io::Result<()> {
+        if self.pending > 0 {
+            self.writer.seek(SeekFrom::Current(self.pending as i64))?;
+            self.pending = 0;
+        }
+        Ok(())
+    }
+
+    // Seeking past the end doesn't make a file any bigger, so if the ROM
+    // ends with a gap, the last byte has to be written to give the file
+    // its full size.
+    fn finish(mut self) -> io::Result<()> {
+        if self.pending > 0 {
+            self.pending -= 1;
+            self.seek_pending()?;
+            self.writer.write_all(&[0])?;
+        }
+        self.writer.flush()
+    }
+}
+
+impl<W: Write + Seek> Write for SparseWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.seek_pending()?;
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl<W: Write + Seek> RomWriter for SparseWriter<W> {
+    fn skip(&mut self, count: u64) -> io::Result<()> {
+        self.pending += count;
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> RomWriter for CisoWriter<W> {
+    fn skip(&mut self, count: u64) -> io::Result<()> {
+        CisoWriter::skip(self, count)
+    }
+}
+
 fn write_zeros(count: usize, mut output: impl Write) -> io::Result<()> {
     lazy_static! {
         static ref ZEROS: Mutex<Vec<u8>> = Mutex::new(vec![]);
@@ -1,6 +1,6 @@
 use std::io::{self, Read, Seek, SeekFrom, Write};
 
-use byteorder::{BigEndian, ReadBytesExt};
+use byteorder::{BigEndian, ByteOrder, ReadBytesExt, WriteBytesExt};
 
 use ::{
     align,
@@ -14,9 +14,24 @@ use sections::Section;
 
 pub const APPLOADER_OFFSET: u64 = 0x2440;
 const APPLOADER_DATE_SIZE: usize = 0x0A;
-const APPLOADER_ENTRY_POINT_ADDR: u64 = 0x2450;
-const APPLOADER_ENTRY_POINT_SIZE: u64 = 0xA0;
 const APPLOADER_SIZE_ADDR: u64 = 0x2454;
+// The code starts right after this, and the trailer right after the code
+pub const APPLOADER_HEADER_SIZE: u64 = 0x20;
+// Where the IPL loads the code and trailer in memory
+pub const APPLOADER_LOAD_ADDRESS: u64 = 0x8120_0000;
+
+// The entry point is only this long in any apploader I've seen
+const MAX_ENTRY_POINT_INSTRUCTIONS: usize = 64;
+const BLR: u32 = 0x4E80_0020;
+
+// The entry point gives the IPL these three functions, by storing
+// their addresses into the pointers it's passed in r3, r4, and r5.
+#[derive(Copy, Clone, Debug)]
+pub struct ApploaderFunctions {
+    pub init: u64,
+    pub main: u64,
+    pub close: u64,
+}
 
 #[derive(Debug)]
 pub struct Apploader {
@@ -24,6 +39,8 @@ pub struct Apploader {
     pub entry_point: u64,
     pub code_size: usize,
     pub trailer_size: usize,
+    // `None` if the entry point didn't look like it normally does
+    pub functions: Option<ApploaderFunctions>,
 }
 
 impl Apploader {
@@ -42,17 +59,90 @@ impl Apploader {
         let code_size = reader.read_u32::<BigEndian>()? as usize;
         let trailer_size = reader.read_u32::<BigEndian>()? as usize;
 
-        Ok(Apploader {
+        let mut apploader = Apploader {
             date,
             entry_point,
             code_size,
             trailer_size,
-        })
+            functions: None,
+        };
+        apploader.functions = apploader.read_code(reader, offset).ok()
+            .and_then(|code| find_functions(&code, entry_point));
+        Ok(apploader)
+    }
+
+    // Reads the code of the apploader at `offset`
+    pub fn read_code<R>(&self, mut reader: R, offset: u64) -> io::Result<Vec<u8>>
+    where
+        R: Read + Seek,
+    {
+        let mut code = Vec::with_capacity(self.code_size);
+        reader.seek(SeekFrom::Start(offset + APPLOADER_HEADER_SIZE))?;
+        reader.take(self.code_size as u64).read_to_end(&mut code)?;
+        if code.len() != self.code_size {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "The apploader is cut off"));
+        }
+        Ok(code)
+    }
+
+    // Relative to the start of the apploader
+    pub fn trailer_offset(&self) -> u64 {
+        APPLOADER_HEADER_SIZE + self.code_size as u64
+    }
+
+    pub fn trailer_address(&self) -> u64 {
+        APPLOADER_LOAD_ADDRESS + self.code_size as u64
+    }
+
+    // Writes a new apploader. `date` should look like 2001/08/23, and
+    // `entry_point` has to be somewhere in `code`, which is loaded at
+    // `APPLOADER_LOAD_ADDRESS`.
+    pub fn write(
+        date: &str,
+        entry_point: u64,
+        code: &[u8],
+        trailer: &[u8],
+        mut writer: impl Write,
+    ) -> io::Result<()> {
+        let is_date = date.len() == APPLOADER_DATE_SIZE && date.char_indices()
+            .all(|(i, c)| if i == 4 || i == 7 { c == '/' } else { c.is_ascii_digit() });
+        if !is_date {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The date should look like YYYY/MM/DD",
+            ));
+        }
+        let code_end = APPLOADER_LOAD_ADDRESS + code.len() as u64;
+        if entry_point < APPLOADER_LOAD_ADDRESS || entry_point >= code_end || entry_point % 4 != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "The entry point must be an instruction in the code, between {:#010x} and {:#010x}",
+                    APPLOADER_LOAD_ADDRESS,
+                    code_end,
+                ),
+            ));
+        }
+
+        let mut header = Vec::with_capacity(APPLOADER_HEADER_SIZE as usize);
+        header.extend_from_slice(date.as_bytes());
+        header.resize(0x10, 0);
+        header.write_u32::<BigEndian>(entry_point as u32)?;
+        header.write_u32::<BigEndian>(code.len() as u32)?;
+        header.write_u32::<BigEndian>(trailer.len() as u32)?;
+        header.resize(APPLOADER_HEADER_SIZE as usize, 0);
+
+        writer.write_all(&header)?;
+        writer.write_all(code)?;
+        writer.write_all(trailer)?;
+        // Apploaders are extracted with their size aligned to 32 bytes
+        let size = APPLOADER_HEADER_SIZE + (code.len() + trailer.len()) as u64;
+        writer.write_all(&vec![0; (align(size, 32) - size) as usize])
     }
 
     pub fn total_size(&self) -> usize {
-        // self.code_size + self.trailer_size
-        align((self.code_size + self.trailer_size) as u64, 32) as usize
+        let size = APPLOADER_HEADER_SIZE + (self.code_size + self.trailer_size) as u64;
+        align(size, 32) as usize
     }
 
     pub fn extract<R, W>(mut iso: R, file: W) -> io::Result<()>
@@ -65,7 +155,7 @@ impl Apploader {
         let trailer_size = iso.read_u32::<BigEndian>()? as u64;
         iso.seek(SeekFrom::Start(APPLOADER_OFFSET))?;
 
-        let aligned_size = align(code_size + trailer_size, 32);
+        let aligned_size = align(APPLOADER_HEADER_SIZE + code_size + trailer_size, 32);
         extract_section(iso, aligned_size as usize, file)
     }
 }
@@ -76,9 +166,23 @@ impl Section for Apploader {
         println!("Date: {}", self.date);
         println!("Code size: {} bytes", format_usize(self.code_size, style));
         println!("Trailer size: {} bytes", format_usize(self.trailer_size, style));
-        println!("Entry point: not yet implemented");
+        println!("Entry point: {}", format_u64(self.entry_point, style));
+        println!("Load address: {}", format_u64(APPLOADER_LOAD_ADDRESS, style));
+        match self.functions {
+            Some(f) => {
+                println!("Init function: {}", format_u64(f.init, style));
+                println!("Main function: {}", format_u64(f.main, style));
+                println!("Close function: {}", format_u64(f.close, style));
+            },
+            None => println!("Functions: couldn't be found from the entry point"),
+        }
         println!(
-            "Size (including code and trailer, aligned to 32 bytes): {}",
+            "Trailer offset: {} (address {})",
+            format_u64(APPLOADER_OFFSET + self.trailer_offset(), style),
+            format_u64(self.trailer_address(), style),
+        );
+        println!(
+            "Size (including header, code and trailer, aligned to 32 bytes): {}",
             format_usize(self.total_size(), style),
         );
     }
@@ -91,3 +195,45 @@ impl Section for Apploader {
         self.total_size()
     }
 }
+
+// Follows the entry point until it returns, keeping track of the values
+// loaded into registers, to find the functions it stores into r3, r4, and r5.
+fn find_functions(code: &[u8], entry_point: u64) -> Option<ApploaderFunctions> {
+    let start = entry_point.checked_sub(APPLOADER_LOAD_ADDRESS)? as usize;
+    let mut registers = [None; 32];
+    let mut functions = [None; 3];
+
+    for word in code.get(start..)?.chunks_exact(4).take(MAX_ENTRY_POINT_INSTRUCTIONS) {
+        let code = BigEndian::read_u32(word);
+        if code == BLR {
+            break;
+        }
+        let opcode = code >> 26;
+        let rd = ((code >> 21) & 0x1f) as usize;
+        let ra = ((code >> 16) & 0x1f) as usize;
+        let imm = code & 0xffff;
+        // For addi and addis, r0 means 0 rather than the register
+        let base = if ra == 0 { Some(0) } else { registers[ra] };
+
+        match opcode {
+            // addi
+            14 => registers[rd] = base.map(|b: u32| b.wrapping_add(imm as i16 as u32)),
+            // addis
+            15 => registers[rd] = base.map(|b: u32| b.wrapping_add(imm << 16)),
+            // ori, where rd is actually the source
+            24 => registers[ra] = registers[rd].map(|v| v | imm),
+            // stw
+            36 if imm == 0 && (3..=5).contains(&ra) => functions[ra - 3] = registers[rd],
+            _ => {},
+        }
+    }
+
+    match functions {
+        [Some(init), Some(main), Some(close)] => Some(ApploaderFunctions {
+            init: init as u64,
+            main: main as u64,
+            close: close as u64,
+        }),
+        _ => None,
+    }
+}
@@ -0,0 +1,428 @@
+// The banner in `opening.bnr` is what the IPL's memory card screen and
+// Dolphin's game list show: a 96x32 image, along with the game's name, its
+// developer, and a description. BNR1 banners have one set of text, while
+// BNR2 banners, used by PAL games, have one for each of six languages.
+// http://hitmen.c02.at/files/yagcd/yagcd/chap14.html#sec14.1
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+
+use byteorder::{BigEndian, ByteOrder};
+use png;
+
+use ::{
+    format_u64,
+    format_usize,
+    NumberStyle,
+};
+use sections::Section;
+use text::TextEncoding;
+
+pub const BANNER_FILENAME: &str = "opening.bnr";
+
+pub const BANNER_WIDTH: usize = 96;
+pub const BANNER_HEIGHT: usize = 32;
+
+const MAGIC_SIZE: usize = 4;
+const IMAGE_OFFSET: usize = 0x20;
+// Two bytes per pixel
+const IMAGE_SIZE: usize = BANNER_WIDTH * BANNER_HEIGHT * 2;
+const METADATA_OFFSET: usize = IMAGE_OFFSET + IMAGE_SIZE;
+const METADATA_SIZE: usize = 0x140;
+
+// The image is stored in 4x4 tiles
+const TILE_SIZE: usize = 4;
+
+// The languages of the metadata in a BNR2 banner, in order
+pub const LANGUAGES: [&str; 6] = ["English", "German", "French", "Spanish", "Italian", "Dutch"];
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BannerVersion {
+    Bnr1,
+    Bnr2,
+}
+
+impl BannerVersion {
+    fn from_magic(magic: &[u8]) -> Option<BannerVersion> {
+        match magic {
+            b"BNR1" => Some(BannerVersion::Bnr1),
+            b"BNR2" => Some(BannerVersion::Bnr2),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BannerVersion::Bnr1 => "BNR1",
+            BannerVersion::Bnr2 => "BNR2",
+        }
+    }
+
+    pub fn metadata_count(self) -> usize {
+        match self {
+            BannerVersion::Bnr1 => 1,
+            BannerVersion::Bnr2 => LANGUAGES.len(),
+        }
+    }
+
+    fn magic(self) -> &'static [u8] {
+        self.name().as_bytes()
+    }
+}
+
+// The strings in the metadata, in the order they're stored
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BannerField {
+    GameName,
+    Developer,
+    FullGameName,
+    FullDeveloper,
+    Description,
+}
+
+impl BannerField {
+    pub fn name(self) -> &'static str {
+        use self::BannerField::*;
+        match self {
+            GameName => "name",
+            Developer => "developer",
+            FullGameName => "full name",
+            FullDeveloper => "full developer",
+            Description => "description",
+        }
+    }
+
+    // Where the string is in the metadata
+    fn range(self) -> Range<usize> {
+        use self::BannerField::*;
+        match self {
+            GameName => 0x00..0x20,
+            Developer => 0x20..0x40,
+            FullGameName => 0x40..0x80,
+            FullDeveloper => 0x80..0xC0,
+            Description => 0xC0..0x140,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct BannerMetadata {
+    pub game_name: String,
+    pub developer: String,
+    pub full_game_name: String,
+    pub full_developer: String,
+    pub description: String,
+    // The metadata as it's stored in the banner, which is what's written
+    pub raw: Vec<u8>,
+}
+
+impl BannerMetadata {
+    fn new(raw: &[u8], encoding: TextEncoding) -> BannerMetadata {
+        use self::BannerField::*;
+        let decode = |field: BannerField| {
+            let field = &raw[field.range()];
+            let len = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+            encoding.decode(&field[..len])
+        };
+
+        BannerMetadata {
+            game_name: decode(GameName),
+            developer: decode(Developer),
+            full_game_name: decode(FullGameName),
+            full_developer: decode(FullDeveloper),
+            description: decode(Description),
+            raw: raw.to_vec(),
+        }
+    }
+
+    // Strings that fill their whole field don't need a null byte
+    pub fn set(&mut self, field: BannerField, text: &str, encoding: TextEncoding) -> io::Result<()> {
+        let range = field.range();
+        let bytes = encoding.encode(text).ok_or_else(|| io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("The {} can't be written in {}", field.name(), encoding.name()),
+        ))?;
+        if bytes.len() > range.len() || bytes.contains(&0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("The {} can't be longer than {} bytes", field.name(), range.len()),
+            ));
+        }
+
+        let raw = &mut self.raw[range];
+        raw[..bytes.len()].copy_from_slice(&bytes);
+        for b in &mut raw[bytes.len()..] {
+            *b = 0;
+        }
+        *self = BannerMetadata::new(&self.raw, encoding);
+        Ok(())
+    }
+
+    fn print_info(&self) {
+        println!("Name: {}", self.game_name);
+        println!("Developer: {}", self.developer);
+        println!("Full name: {}", self.full_game_name);
+        println!("Full developer: {}", self.full_developer);
+        println!("Description: {}", self.description);
+    }
+}
+
+#[derive(Debug)]
+pub struct Banner {
+    pub offset: u64,
+    pub version: BannerVersion,
+    // The image as it's stored, in RGB5A3 and split into tiles
+    pub image: Vec<u8>,
+    pub metadata: Vec<BannerMetadata>,
+}
+
+impl Banner {
+    // The text is decoded with `encoding`, which comes from the header
+    pub fn new<R>(mut reader: R, offset: u64, encoding: TextEncoding) -> io::Result<Banner>
+    where
+        R: Read + Seek,
+    {
+        reader.seek(SeekFrom::Start(offset))?;
+        let mut magic = [0; MAGIC_SIZE];
+        reader.read_exact(&mut magic)?;
+        let version = BannerVersion::from_magic(&magic)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Not a banner file"))?;
+
+        reader.seek(SeekFrom::Start(offset + IMAGE_OFFSET as u64))?;
+        let mut image = vec![0; IMAGE_SIZE];
+        reader.read_exact(&mut image)?;
+
+        let mut raw = vec![0; METADATA_SIZE];
+        let mut metadata = Vec::with_capacity(version.metadata_count());
+        for _ in 0..version.metadata_count() {
+            reader.read_exact(&mut raw)?;
+            metadata.push(BannerMetadata::new(&raw, encoding));
+        }
+
+        Ok(Banner {
+            offset,
+            version,
+            image,
+            metadata,
+        })
+    }
+
+    // A banner with a blank image and no text
+    pub fn blank(version: BannerVersion) -> Banner {
+        let encoding = TextEncoding::Windows1252;
+        Banner {
+            offset: 0,
+            version,
+            image: vec![0; IMAGE_SIZE],
+            metadata: (0..version.metadata_count())
+                .map(|_| BannerMetadata::new(&[0; METADATA_SIZE], encoding))
+                .collect(),
+        }
+    }
+
+    // The metadata to show when only one language is needed
+    pub fn default_metadata(&self) -> &BannerMetadata {
+        &self.metadata[0]
+    }
+
+    // Converts the image to 8-bit RGBA, one row after another
+    pub fn to_rgba(&self) -> Vec<u8> {
+        let mut rgba = vec![0; BANNER_WIDTH * BANNER_HEIGHT * 4];
+        for (i, pixel) in self.image.chunks(2).enumerate() {
+            let (x, y) = tiled_position(i);
+            let start = (y * BANNER_WIDTH + x) * 4;
+            rgba[start..start + 4].copy_from_slice(&rgb5a3_to_rgba(BigEndian::read_u16(pixel)));
+        }
+        rgba
+    }
+
+    // Replaces the image with `rgba`, which is 8-bit RGBA, one row after another
+    pub fn set_rgba(&mut self, rgba: &[u8]) -> io::Result<()> {
+        if rgba.len() != BANNER_WIDTH * BANNER_HEIGHT * 4 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Banner images have to be {}x{}", BANNER_WIDTH, BANNER_HEIGHT),
+            ));
+        }
+        for (i, pixel) in self.image.chunks_mut(2).enumerate() {
+            let (x, y) = tiled_position(i);
+            let start = (y * BANNER_WIDTH + x) * 4;
+            BigEndian::write_u16(pixel, rgba_to_rgb5a3(&rgba[start..start + 4]));
+        }
+        Ok(())
+    }
+
+    pub fn write(&self, mut writer: impl Write) -> io::Result<()> {
+        writer.write_all(self.version.magic())?;
+        writer.write_all(&[0; IMAGE_OFFSET - MAGIC_SIZE])?;
+        writer.write_all(&self.image)?;
+        for metadata in &self.metadata {
+            writer.write_all(&metadata.raw)?;
+        }
+        Ok(())
+    }
+
+    pub fn write_png(&self, writer: impl Write) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(writer, BANNER_WIDTH as u32, BANNER_HEIGHT as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.to_rgba())?;
+        Ok(())
+    }
+}
+
+// Decodes a PNG to 8-bit RGBA, and returns it along with its width and height
+pub fn decode_png(reader: impl Read) -> io::Result<(usize, usize, Vec<u8>)> {
+    let mut decoder = png::Decoder::new(reader);
+    // Palettes and bit depths other than 8 are converted to 8-bit colors
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf)?;
+
+    let pixels = &buf[..info.buffer_size()];
+    let rgba: Vec<u8> = match info.color_type {
+        png::ColorType::Rgba => pixels.to_vec(),
+        png::ColorType::Rgb => pixels.chunks(3).flat_map(|p| vec![p[0], p[1], p[2], 0xFF]).collect(),
+        png::ColorType::GrayscaleAlpha => pixels.chunks(2).flat_map(|p| vec![p[0], p[0], p[0], p[1]]).collect(),
+        png::ColorType::Grayscale => pixels.iter().flat_map(|&p| vec![p, p, p, 0xFF]).collect(),
+        png::ColorType::Indexed => return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "The PNG's palette couldn't be read",
+        )),
+    };
+    Ok((info.width as usize, info.height as usize, rgba))
+}
+
+// The position in the image of the `i`th pixel in the tiled data
+fn tiled_position(i: usize) -> (usize, usize) {
+    let tiles_per_row = BANNER_WIDTH / TILE_SIZE;
+    let tile = i / (TILE_SIZE * TILE_SIZE);
+    let in_tile = i % (TILE_SIZE * TILE_SIZE);
+    (
+        (tile % tiles_per_row) * TILE_SIZE + in_tile % TILE_SIZE,
+        (tile / tiles_per_row) * TILE_SIZE + in_tile / TILE_SIZE,
+    )
+}
+
+// If the top bit is set, the color is RGB555 and opaque. Otherwise,
+// it's 3 bits of alpha followed by RGB444.
+fn rgb5a3_to_rgba(color: u16) -> [u8; 4] {
+    // Scales a `bits`-bit value up to 8 bits
+    let scale = |value: u16, bits: u32| -> u8 {
+        let max = (1 << bits) - 1;
+        ((value & max) as u32 * 255 / max as u32) as u8
+    };
+    if color & 0x8000 != 0 {
+        [scale(color >> 10, 5), scale(color >> 5, 5), scale(color, 5), 0xFF]
+    } else {
+        [scale(color >> 8, 4), scale(color >> 4, 4), scale(color, 4), scale(color >> 12, 3)]
+    }
+}
+
+// Colors that are almost opaque are stored as opaque, since that
+// gives them more bits for each color.
+fn rgba_to_rgb5a3(rgba: &[u8]) -> u16 {
+    let (r, g, b, a) = (rgba[0] as u16, rgba[1] as u16, rgba[2] as u16, rgba[3] as u16);
+    if a >= 0xE0 {
+        0x8000 | (r >> 3) << 10 | (g >> 3) << 5 | b >> 3
+    } else {
+        (a >> 5) << 12 | (r >> 4) << 8 | (g >> 4) << 4 | b >> 4
+    }
+}
+
+impl Section for Banner {
+    fn print_info(&self, style: NumberStyle) {
+        println!("Offset: {}", format_u64(self.offset, style));
+        println!("Size: {} bytes", format_usize(self.size(), style));
+        println!("Version: {}", self.version.name());
+        match self.version {
+            BannerVersion::Bnr1 => self.metadata[0].print_info(),
+            BannerVersion::Bnr2 => {
+                for (metadata, language) in self.metadata.iter().zip(LANGUAGES.iter()) {
+                    println!();
+                    println!("{}:", language);
+                    metadata.print_info();
+                }
+            },
+        }
+    }
+
+    fn start(&self) -> u64 {
+        self.offset
+    }
+
+    fn size(&self) -> usize {
+        METADATA_OFFSET + METADATA_SIZE * self.metadata.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The RGBA of the pixel at (x, y)
+    fn pixel(rgba: &[u8], x: usize, y: usize) -> &[u8] {
+        let start = (y * BANNER_WIDTH + x) * 4;
+        &rgba[start..start + 4]
+    }
+
+    #[test]
+    fn decode_tile() {
+        // The first tile is the top left 4x4 pixels, one row after another.
+        // The top row is opaque and the second row has alpha.
+        let tile: [u16; 16] = [
+            0xFC00, 0x83E0, 0x801F, 0xFFFF,
+            0x3F80, 0x0000, 0x7FFF, 0x1234,
+            0, 0, 0, 0,
+            0, 0, 0, 0,
+        ];
+        let mut banner = Banner::blank(BannerVersion::Bnr1);
+        for (i, &color) in tile.iter().enumerate() {
+            BigEndian::write_u16(&mut banner.image[i * 2..], color);
+        }
+        // The first pixel of the next tile, to the right of this one
+        BigEndian::write_u16(&mut banner.image[tile.len() * 2..], 0xFFFF);
+
+        let rgba = banner.to_rgba();
+        assert_eq!(pixel(&rgba, 0, 0), [0xFF, 0x00, 0x00, 0xFF]);
+        assert_eq!(pixel(&rgba, 1, 0), [0x00, 0xFF, 0x00, 0xFF]);
+        assert_eq!(pixel(&rgba, 2, 0), [0x00, 0x00, 0xFF, 0xFF]);
+        assert_eq!(pixel(&rgba, 3, 0), [0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(pixel(&rgba, 0, 1), [0xFF, 0x88, 0x00, 0x6D]);
+        assert_eq!(pixel(&rgba, 1, 1), [0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(pixel(&rgba, 2, 1), [0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(pixel(&rgba, 3, 1), [0x22, 0x33, 0x44, 0x24]);
+        assert_eq!(pixel(&rgba, 4, 0), [0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(pixel(&rgba, 0, 2), [0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(pixel(&rgba, 0, 4), [0x00, 0x00, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn set_rgba_round_trip() {
+        // Colors with the top bit set are opaque. The others have less than
+        // full alpha, since those would be stored as opaque.
+        let mut banner = Banner::blank(BannerVersion::Bnr1);
+        for (i, color) in banner.image.chunks_mut(2).enumerate() {
+            let value = if i % 2 == 0 {
+                0x8000 | (i * 37) as u16 & 0x7FFF
+            } else {
+                (i * 53) as u16 & 0x6FFF
+            };
+            BigEndian::write_u16(color, value);
+        }
+
+        let rgba = banner.to_rgba();
+        let mut copy = Banner::blank(BannerVersion::Bnr1);
+        copy.set_rgba(&rgba).unwrap();
+        assert_eq!(copy.image, banner.image);
+        assert_eq!(copy.to_rgba(), rgba);
+    }
+
+    #[test]
+    fn set_rgba_wrong_size() {
+        let mut banner = Banner::blank(BannerVersion::Bnr1);
+        assert!(banner.set_rgba(&[0; 4]).is_err());
+    }
+}
@@ -0,0 +1,236 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use byteorder::{BigEndian, WriteBytesExt};
+
+use ::align;
+use super::{DOLHeader, DATA_SEG_COUNT, DOL_HEADER_LEN, TEXT_SEG_COUNT};
+use super::segment::SegmentType;
+
+// Segments are placed at offsets that are a multiple of this when writing
+const SEGMENT_ALIGNMENT: u64 = 32;
+
+#[derive(Clone, Debug)]
+pub struct DolSegment {
+    pub seg_type: SegmentType,
+    pub seg_num: u64,
+    pub loading_address: u64,
+    pub data: Vec<u8>,
+}
+
+impl DolSegment {
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn end_address(&self) -> u64 {
+        self.loading_address + self.data.len() as u64
+    }
+
+    pub fn contains_addr(&self, mem_addr: u64) -> bool {
+        self.loading_address <= mem_addr && mem_addr < self.end_address()
+    }
+
+    pub fn name(&self) -> String {
+        self.seg_type.to_string(self.seg_num)
+    }
+}
+
+// A DOL file that owns the data for all of its segments, so it can be
+// modified and written back out. `DOLHeader` only knows where things are.
+#[derive(Clone, Debug)]
+pub struct Dol {
+    pub entry_point: u64,
+    pub bss_address: u64,
+    pub bss_size: usize,
+    // Sorted by type (text segments first), then number
+    segments: Vec<DolSegment>,
+}
+
+impl Dol {
+    pub fn new(entry_point: u64) -> Dol {
+        Dol {
+            entry_point,
+            bss_address: 0,
+            bss_size: 0,
+            segments: Vec::new(),
+        }
+    }
+
+    // Reads the DOL that starts at `offset`, including all of its segments.
+    pub fn read<R>(mut file: R, offset: u64) -> io::Result<Dol>
+    where
+        R: Read + Seek,
+    {
+        let header = DOLHeader::new(&mut file, offset)?;
+
+        let mut segments = Vec::new();
+        for s in header.iter_segments() {
+            let mut data = Vec::with_capacity(s.size);
+            file.seek(SeekFrom::Start(s.offset))?;
+            (&mut file).take(s.size as u64).read_to_end(&mut data)?;
+            if data.len() != s.size {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("{} is cut off", s.to_string()),
+                ));
+            }
+            segments.push(DolSegment {
+                seg_type: s.seg_type,
+                seg_num: s.seg_num,
+                loading_address: s.loading_address,
+                data,
+            });
+        }
+
+        Ok(Dol {
+            entry_point: header.entry_point,
+            bss_address: header.bss_address,
+            bss_size: header.bss_size,
+            segments,
+        })
+    }
+
+    pub fn iter_segments(&self) -> impl Iterator<Item = &DolSegment> {
+        self.segments.iter()
+    }
+
+    pub fn find_segment(&self, seg_type: SegmentType, number: u64) -> Option<&DolSegment> {
+        self.segments.iter()
+            .find(|s| s.seg_type == seg_type && s.seg_num == number)
+    }
+
+    pub fn find_segment_mut(&mut self, seg_type: SegmentType, number: u64) -> Option<&mut DolSegment> {
+        self.segments.iter_mut()
+            .find(|s| s.seg_type == seg_type && s.seg_num == number)
+    }
+
+    pub fn segment_at_addr(&self, mem_addr: u64) -> Option<&DolSegment> {
+        self.segments.iter().find(|s| s.contains_addr(mem_addr))
+    }
+
+    pub fn segment_at_addr_mut(&mut self, mem_addr: u64) -> Option<&mut DolSegment> {
+        self.segments.iter_mut().find(|s| s.contains_addr(mem_addr))
+    }
+
+    // Adds a segment in the first unused slot for its type,
+    // and returns its number.
+    pub fn add_segment(
+        &mut self,
+        seg_type: SegmentType,
+        loading_address: u64,
+        data: Vec<u8>,
+    ) -> io::Result<u64> {
+        let max_count = match seg_type {
+            SegmentType::Text => TEXT_SEG_COUNT,
+            SegmentType::Data => DATA_SEG_COUNT,
+        } as u64;
+        let seg_num = (0..max_count)
+            .find(|&n| self.find_segment(seg_type, n).is_none())
+            .ok_or_else(|| io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("There's no room for another {} segment", match seg_type {
+                    SegmentType::Text => "text",
+                    SegmentType::Data => "data",
+                }),
+            ))?;
+
+        self.segments.push(DolSegment {
+            seg_type,
+            seg_num,
+            loading_address,
+            data,
+        });
+        self.segments.sort_by_key(|s| (s.seg_type == SegmentType::Data, s.seg_num));
+        Ok(seg_num)
+    }
+
+    pub fn remove_segment(&mut self, seg_type: SegmentType, number: u64) -> Option<DolSegment> {
+        self.segments.iter()
+            .position(|s| s.seg_type == seg_type && s.seg_num == number)
+            .map(|i| self.segments.remove(i))
+    }
+
+    // Grows the segment with zeros or cuts off its end
+    pub fn resize_segment(
+        &mut self,
+        seg_type: SegmentType,
+        number: u64,
+        size: usize,
+    ) -> io::Result<()> {
+        self.find_segment_mut(seg_type, number)
+            .map(|s| s.data.resize(size, 0))
+            .ok_or_else(|| missing_segment(seg_type, number))
+    }
+
+    pub fn relocate_segment(
+        &mut self,
+        seg_type: SegmentType,
+        number: u64,
+        loading_address: u64,
+    ) -> io::Result<()> {
+        self.find_segment_mut(seg_type, number)
+            .map(|s| s.loading_address = loading_address)
+            .ok_or_else(|| missing_segment(seg_type, number))
+    }
+
+    // The offset of each segment relative to the start of the DOL,
+    // in the same order as `iter_segments`.
+    fn segment_offsets(&self) -> Vec<u64> {
+        let mut offset = DOL_HEADER_LEN as u64;
+        self.segments.iter().map(|s| {
+            let o = offset;
+            offset = align(offset + s.size() as u64, SEGMENT_ALIGNMENT);
+            o
+        }).collect()
+    }
+
+    pub fn size(&self) -> usize {
+        self.segment_offsets().iter().zip(&self.segments)
+            .map(|(&offset, s)| offset as usize + s.size())
+            .max()
+            .unwrap_or(DOL_HEADER_LEN)
+    }
+
+    pub fn write(&self, mut writer: impl Write) -> io::Result<()> {
+        let offsets = self.segment_offsets();
+
+        let mut seg_offsets = [0; TEXT_SEG_COUNT + DATA_SEG_COUNT];
+        let mut seg_addresses = [0; TEXT_SEG_COUNT + DATA_SEG_COUNT];
+        let mut seg_sizes = [0; TEXT_SEG_COUNT + DATA_SEG_COUNT];
+        for (s, &offset) in self.segments.iter().zip(&offsets) {
+            let i = match s.seg_type {
+                SegmentType::Text => s.seg_num as usize,
+                SegmentType::Data => TEXT_SEG_COUNT + s.seg_num as usize,
+            };
+            seg_offsets[i] = offset as u32;
+            seg_addresses[i] = s.loading_address as u32;
+            seg_sizes[i] = s.size() as u32;
+        }
+
+        let mut header = Vec::with_capacity(DOL_HEADER_LEN);
+        for &n in seg_offsets.iter().chain(&seg_addresses).chain(&seg_sizes) {
+            header.write_u32::<BigEndian>(n)?;
+        }
+        header.write_u32::<BigEndian>(self.bss_address as u32)?;
+        header.write_u32::<BigEndian>(self.bss_size as u32)?;
+        header.write_u32::<BigEndian>(self.entry_point as u32)?;
+        header.resize(DOL_HEADER_LEN, 0);
+        writer.write_all(&header)?;
+
+        let mut position = DOL_HEADER_LEN as u64;
+        for (s, &offset) in self.segments.iter().zip(&offsets) {
+            let padding = vec![0; (offset - position) as usize];
+            writer.write_all(&padding)?;
+            writer.write_all(&s.data)?;
+            position = offset + s.size() as u64;
+        }
+        Ok(())
+    }
+}
+
+fn missing_segment(seg_type: SegmentType, number: u64) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("There is no {} segment", seg_type.to_string(number)),
+    )
+}
@@ -1,4 +1,6 @@
+pub mod editable;
 pub mod segment;
+mod validation;
 
 use std::cmp::max;
 use std::io::{self, Read, Seek, SeekFrom, Write};
@@ -15,20 +17,27 @@ use ::{
 
 use self::segment::{Segment, SegmentType};
 
+pub use self::editable::{Dol, DolSegment};
+pub use self::validation::{DOLDiagnostic, MEMORY_END, MEMORY_START};
+
 use sections::Section;
 
-const TEXT_SEG_COUNT: usize = 7;
-const DATA_SEG_COUNT: usize = 11;
+pub const TEXT_SEG_COUNT: usize = 7;
+pub const DATA_SEG_COUNT: usize = 11;
 const TOTAL_SEG_COUNT: usize = TEXT_SEG_COUNT + DATA_SEG_COUNT;
 
 pub const DOL_OFFSET_OFFSET: u64 = 0x0420;
 pub const DOL_HEADER_LEN: usize = 0x100;
 
+const BSS_ADDRESS_OFFSET: u64 = 0xD8;
+
 #[derive(Debug)]
 pub struct DOLHeader {
     pub offset: u64,
     pub dol_size: usize,
     pub entry_point: u64,
+    pub bss_address: u64,
+    pub bss_size: usize,
     segments: Vec<Segment>,
     // This is the index in `segments` where the data segments are. The segments
     // before this index are all text segments.
@@ -47,9 +56,11 @@ impl DOLHeader {
         let mut is_text = true;
         for i in 0..TOTAL_SEG_COUNT {
             let mut num = i as u64;
-            if i >= TEXT_SEG_COUNT {
+            if i == TEXT_SEG_COUNT {
                 is_text = false;
                 data_segments_index = segments.len();
+            }
+            if !is_text {
                 num -= TEXT_SEG_COUNT as u64;
             }
             let size = file.read_u32::<BigEndian>()? as usize;
@@ -88,16 +99,21 @@ impl DOLHeader {
             s.loading_address = file.read_u32::<BigEndian>()? as u64;
         }
 
-        file.seek(SeekFrom::Start(offset + 0xE0))?;
+        file.seek(SeekFrom::Start(offset + BSS_ADDRESS_OFFSET))?;
+        let bss_address = file.read_u32::<BigEndian>()? as u64;
+        let bss_size = file.read_u32::<BigEndian>()? as usize;
         let entry_point = file.read_u32::<BigEndian>()? as u64;
 
         let dol_size = segments.iter()
-            .map(|s| (s.offset - offset) as usize + s.size).max().unwrap();
+            .map(|s| (s.offset - offset) as usize + s.size).max()
+            .unwrap_or(DOL_HEADER_LEN);
 
         Ok(DOLHeader {
             offset,
             dol_size,
             entry_point,
+            bss_address,
+            bss_size,
             segments,
             data_segments_index,
         })
@@ -168,6 +184,16 @@ impl Section for DOLHeader {
         println!("Size: {} bytes", format_usize(self.dol_size, style));
         println!("Header Size: {} bytes", format_usize(DOL_HEADER_LEN, style));
         println!("Entry point: {}", format_u64(self.entry_point, style));
+        println!("BSS address: {}", format_u64(self.bss_address, style));
+        println!("BSS size: {} bytes", format_usize(self.bss_size, style));
+
+        let diagnostics = self.validate();
+        if !diagnostics.is_empty() {
+            println!("Problems:");
+            for d in diagnostics {
+                println!("  {}", d);
+            }
+        }
         println!("Segments:");
         for s in &self.segments {
             println!();
@@ -0,0 +1,134 @@
+use std::fmt;
+
+use super::{DOLHeader, DOL_HEADER_LEN};
+use super::segment::{Segment, SegmentType};
+
+// The range of addresses a DOL can be loaded into (cached MEM1)
+pub const MEMORY_START: u64 = 0x8000_0000;
+pub const MEMORY_END: u64 = 0x8180_0000;
+
+// The apploader reads segments straight into memory, and disc reads have to go
+// to 32-byte aligned addresses from offsets that are a multiple of 4.
+const ADDRESS_ALIGNMENT: u64 = 32;
+const OFFSET_ALIGNMENT: u64 = 4;
+
+// Something wrong with a DOL that will probably keep it from loading
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DOLDiagnostic {
+    NoTextSegments,
+    EntryPointOutsideText { entry_point: u64 },
+    // Two segments that would be loaded into the same memory
+    OverlappingSegments { first: String, second: String },
+    // Two segments that are stored in the same part of the file
+    OverlappingData { first: String, second: String },
+    SegmentInHeader { segment: String, offset: u64 },
+    MisalignedOffset { segment: String, offset: u64 },
+    MisalignedAddress { segment: String, address: u64 },
+    SegmentOutsideMemory { segment: String, start: u64, end: u64 },
+    BSSOutsideMemory { start: u64, end: u64 },
+}
+
+impl fmt::Display for DOLDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::DOLDiagnostic::*;
+        match self {
+            NoTextSegments =>
+                write!(f, "There are no text segments"),
+            EntryPointOutsideText { entry_point } =>
+                write!(f, "The entry point ({:#010x}) isn't in a text segment", entry_point),
+            OverlappingSegments { first, second } =>
+                write!(f, "{} and {} overlap in memory", first, second),
+            OverlappingData { first, second } =>
+                write!(f, "{} and {} overlap in the file", first, second),
+            SegmentInHeader { segment, offset } =>
+                write!(f, "{} starts inside the header (offset {:#x})", segment, offset),
+            MisalignedOffset { segment, offset } =>
+                write!(f, "{}'s offset ({:#x}) isn't a multiple of {}", segment, offset, OFFSET_ALIGNMENT),
+            MisalignedAddress { segment, address } =>
+                write!(f, "{}'s address ({:#010x}) isn't a multiple of {}", segment, address, ADDRESS_ALIGNMENT),
+            SegmentOutsideMemory { segment, start, end } =>
+                write!(f, "{} ({:#010x}-{:#010x}) is outside of main memory", segment, start, end),
+            BSSOutsideMemory { start, end } =>
+                write!(f, "The BSS ({:#010x}-{:#010x}) is outside of main memory", start, end),
+        }
+    }
+}
+
+fn outside_memory(start: u64, end: u64) -> bool {
+    start < MEMORY_START || end > MEMORY_END
+}
+
+fn overlaps(a: (u64, u64), b: (u64, u64)) -> bool {
+    a.0 < b.1 && b.0 < a.1
+}
+
+impl DOLHeader {
+    // Checks for problems that would keep the DOL from loading or running
+    // correctly. An empty list means everything looks fine.
+    pub fn validate(&self) -> Vec<DOLDiagnostic> {
+        use self::DOLDiagnostic::*;
+
+        let mut diagnostics = Vec::new();
+        let memory_range = |s: &Segment| (s.loading_address, s.loading_address + s.size as u64);
+        // Relative to the start of the DOL
+        let file_range = |s: &Segment| {
+            let start = s.offset - self.offset;
+            (start, start + s.size as u64)
+        };
+
+        let mut text_segments = self.segments.iter()
+            .filter(|s| s.seg_type == SegmentType::Text)
+            .peekable();
+        if text_segments.peek().is_none() {
+            diagnostics.push(NoTextSegments);
+        } else if !text_segments.any(|s| {
+            let (start, end) = memory_range(s);
+            start <= self.entry_point && self.entry_point < end
+        }) {
+            diagnostics.push(EntryPointOutsideText { entry_point: self.entry_point });
+        }
+
+        for s in &self.segments {
+            let (offset, _) = file_range(s);
+            let (start, end) = memory_range(s);
+            if offset < DOL_HEADER_LEN as u64 {
+                diagnostics.push(SegmentInHeader { segment: s.to_string(), offset });
+            }
+            if offset % OFFSET_ALIGNMENT != 0 {
+                diagnostics.push(MisalignedOffset { segment: s.to_string(), offset });
+            }
+            if start % ADDRESS_ALIGNMENT != 0 {
+                diagnostics.push(MisalignedAddress { segment: s.to_string(), address: start });
+            }
+            if outside_memory(start, end) {
+                diagnostics.push(SegmentOutsideMemory { segment: s.to_string(), start, end });
+            }
+        }
+
+        for (i, a) in self.segments.iter().enumerate() {
+            for b in &self.segments[i + 1..] {
+                if overlaps(memory_range(a), memory_range(b)) {
+                    diagnostics.push(OverlappingSegments {
+                        first: a.to_string(),
+                        second: b.to_string(),
+                    });
+                }
+                if overlaps(file_range(a), file_range(b)) {
+                    diagnostics.push(OverlappingData {
+                        first: a.to_string(),
+                        second: b.to_string(),
+                    });
+                }
+            }
+        }
+
+        // The BSS usually covers some of the small data segments,
+        // so it's only checked against the bounds of memory.
+        let bss_end = self.bss_address + self.bss_size as u64;
+        if self.bss_size > 0 && outside_memory(self.bss_address, bss_end) {
+            diagnostics.push(BSSOutsideMemory { start: self.bss_address, end: bss_end });
+        }
+
+        diagnostics
+    }
+}
@@ -1,3 +1,4 @@
+use std::ffi::OsStr;
 use std::fs::{create_dir_all, File};
 use std::io::{self, BufRead, Seek, SeekFrom, Write};
 use std::path::{self, Path, PathBuf};
@@ -6,6 +7,7 @@ use byteorder::{BigEndian, ReadBytesExt};
 
 use ::{extract_section, format_u64, format_usize, NumberStyle};
 use sections::Section;
+use text::TextEncoding;
 
 pub const ENTRY_SIZE: usize = 12;
 
@@ -20,6 +22,8 @@ fn write_int_to_buffer(num: u64, buf: &mut [u8]) {
 pub struct EntryInfo {
     pub index: usize,
     pub name: String,
+    // The name as it's stored in the string table, which is what's written
+    pub raw_name: Vec<u8>,
     pub filename_offset: u64,
 
     // The fields below are not actually stored on the ROM:
@@ -30,6 +34,14 @@ pub struct EntryInfo {
     pub full_path: PathBuf,
 }
 
+impl EntryInfo {
+    // Directory names end with a separator, but that
+    // shouldn't be needed to find them by name.
+    pub fn has_name(&self, name: impl AsRef<OsStr>) -> bool {
+        OsStr::new(self.name.trim_end_matches(path::MAIN_SEPARATOR)) == name.as_ref()
+    }
+}
+
 #[derive(Debug)]
 pub struct FileEntry {
     pub info: EntryInfo,
@@ -80,6 +92,7 @@ impl Entry {
         let info = EntryInfo {
             index,
             name,
+            raw_name: Vec::new(),
             filename_offset,
             directory_index,
             full_path,
@@ -181,6 +194,7 @@ impl Entry {
         &mut self,
         mut reader: impl BufRead + Seek,
         str_tbl_addr: u64,
+        encoding: TextEncoding,
     ) -> io::Result<()> {
         let is_directory = self.is_dir();
         let info = self.info_mut();
@@ -190,7 +204,13 @@ impl Entry {
             reader.seek(SeekFrom::Start(str_tbl_addr + info.filename_offset))?;
             let mut bytes = Vec::new();
             reader.read_until(0, &mut bytes)?;
-            info.name = String::from_utf8(bytes).unwrap_or_else(|_| String::new());
+            // The terminator isn't part of the name, and `raw_name` gets a
+            // new one when it's written
+            if bytes.last() == Some(&0) {
+                bytes.pop();
+            }
+            info.name = encoding.decode(&bytes);
+            info.raw_name = bytes;
             if is_directory {
                 info.name.push(path::MAIN_SEPARATOR);
             }
@@ -8,6 +8,7 @@ use std::path::{Path, PathBuf};
 use byteorder::{BigEndian, ReadBytesExt};
 
 use sections::Section;
+use text::TextEncoding;
 use ::{
     extract_section,
     format_u64,
@@ -35,7 +36,8 @@ pub struct FST {
 }
 
 impl FST {
-    pub fn new(mut iso: impl BufRead + Seek, offset: u64) -> io::Result<FST> {
+    // File names are decoded with `encoding`, which comes from the header
+    pub fn new(mut iso: impl BufRead + Seek, offset: u64, encoding: TextEncoding) -> io::Result<FST> {
         let mut iso = &mut iso;
         iso.seek(SeekFrom::Start(offset))?;
 
@@ -87,7 +89,7 @@ impl FST {
 
         let mut end = 0;
         for e in entries.iter_mut() {
-            e.read_filename(&mut iso, str_tbl_addr)?;
+            e.read_filename(&mut iso, str_tbl_addr, encoding)?;
 
             let curr_end = iso.seek(SeekFrom::Current(0))?;
             end = max(curr_end, end);
@@ -142,11 +144,11 @@ impl FST {
         let mut sorted_names = BTreeMap::new();
         for e in &self.entries {
             e.write(&mut writer)?;
-            sorted_names.insert(e.info().filename_offset, &e.info().name);
+            sorted_names.insert(e.info().filename_offset, &e.info().raw_name);
         }
         let null_byte = [0];
         for (_, name) in &sorted_names {
-            (&mut writer).write(name.as_bytes())?;
+            (&mut writer).write(name)?;
             (&mut writer).write(&null_byte[..])?;
         }
         Ok(())
@@ -154,15 +156,16 @@ impl FST {
 
     pub fn entry_for_path(&self, path: impl AsRef<Path>) -> Option<&Entry> {
         let path = path.as_ref();
-        if path.is_relative() {
+        if path.is_relative() && path.components().count() == 1 {
             // Just treat the entire `path` like a single filename in this case
             self.entry_with_name(path, self.root())
         } else {
             // For each component in `path` (skipping the initial "/"),
             // try to find the corresponding file with that name
-            path.iter().skip(1).try_fold(&self.entries[0], |entry, name| {
+            let skip = if path.has_root() { 1 } else { 0 };
+            path.iter().skip(skip).try_fold(&self.entries[0], |entry, name| {
                 entry.as_dir().and_then(|dir| {
-                    dir.iter_contents(&self.entries).find(|e| &e.info().name[..] == name)
+                    dir.iter_contents(&self.entries).find(|e| e.info().has_name(name))
                 })
             })
         }
@@ -171,7 +174,7 @@ impl FST {
     fn entry_with_name<'a>(&'a self, name: impl AsRef<Path>, dir: &'a DirectoryEntry) -> Option<&'a Entry> {
         let name = name.as_ref();
         dir.iter_contents(&self.entries).find_map(|e| {
-            if name.as_os_str() == &e.info().name[..] {
+            if e.info().has_name(name.as_os_str()) {
                 Some(e)
             } else {
                 e.as_dir().and_then(|subdir| self.entry_with_name(name, subdir))
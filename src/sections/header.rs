@@ -13,8 +13,12 @@ use ::{
 };
 
 use sections::Section;
+use text::TextEncoding;
 
 pub const GAME_HEADER_SIZE: usize = 0x2440;
+// Where the disk header information starts
+pub const HEADER_INFORMATION_OFFSET: usize = 0x440;
+pub const HEADER_INFORMATION_SIZE: usize = 0x20;
 
 pub const GAMEID_SIZE: usize = 6;
 pub const GAMEID_OFFSET: u64 = 0;
@@ -55,6 +59,9 @@ pub struct Header {
     pub audio_streaming: u8,
     pub stream_buffer_size: u8,
     pub title: String,
+    // The title as it's stored in the ROM, without the null byte. This is
+    // what's written, so use `set_title` rather than changing `title`.
+    pub raw_title: Vec<u8>,
     pub debug_monitor_offset: u32,
     pub debug_monitor_load_addr: u32,
     pub dol_offset: u64, // technically u32, but u64 is easier to work with
@@ -79,6 +86,75 @@ pub const TRACK_SIZE_SIZE: usize = 4;
 pub const COUNTRY_CODE_SIZE: usize = 4;
 pub const INFO_UNKNOWN_SIZE: usize = 4;
 
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Region {
+    NtscJ,
+    NtscU,
+    Pal,
+}
+
+impl Region {
+    pub fn parse(name: &str) -> Option<Region> {
+        use self::Region::*;
+        match name.to_lowercase().as_str() {
+            "ntsc-j" | "ntscj" | "jp" => Some(NtscJ),
+            "ntsc-u" | "ntscu" | "us" => Some(NtscU),
+            "pal" | "eu" => Some(Pal),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        use self::Region::*;
+        match self {
+            NtscJ => "NTSC-J",
+            NtscU => "NTSC-U",
+            Pal => "PAL",
+        }
+    }
+
+    pub fn from_country_code(country_code: u32) -> Option<Region> {
+        use self::Region::*;
+        match country_code {
+            0 => Some(NtscJ),
+            1 => Some(NtscU),
+            2 => Some(Pal),
+            _ => None,
+        }
+    }
+
+    pub fn country_code(self) -> u32 {
+        use self::Region::*;
+        match self {
+            NtscJ => 0,
+            NtscU => 1,
+            Pal => 2,
+        }
+    }
+
+    // The last letter of the game code. PAL games use a different letter for
+    // each language (D for German, F for French, and so on), and Korean games
+    // use K, but they're run as PAL and NTSC-J games respectively.
+    pub fn from_game_code_letter(letter: char) -> Option<Region> {
+        use self::Region::*;
+        match letter {
+            'J' | 'K' => Some(NtscJ),
+            'E' => Some(NtscU),
+            'P' | 'D' | 'F' | 'H' | 'I' | 'S' | 'U' | 'X' | 'Y' => Some(Pal),
+            _ => None,
+        }
+    }
+
+    pub fn game_code_letter(self) -> char {
+        use self::Region::*;
+        match self {
+            NtscJ => 'J',
+            NtscU => 'E',
+            Pal => 'P',
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct HeaderInformation {
     pub debug_monitor_size: u32,
@@ -158,10 +234,7 @@ impl Header {
             let last_index = title.len() - 1;
             title.remove(last_index);
         }
-        let title = String::from_utf8(title).map_err(|_| io::Error::new(
-            io::ErrorKind::InvalidData,
-            "ROM Title was not valid UTF-8",
-        ))?;
+        let raw_title = title;
 
         file.seek(
             SeekFrom::Current(GAME_NAME_SIZE as i64 - bytes_read as i64)
@@ -182,18 +255,22 @@ impl Header {
         let user_length = file.read_u32::<BigEndian>()?;
         let unknown = file.read_u32::<BigEndian>()?;
 
+        // The disk header information starts at `HEADER_INFORMATION_OFFSET`,
+        // after 4 unused bytes
+        file.seek(SeekFrom::Current(UNUSED_REGION_3_SIZE as i64))?;
         let pos = file.seek(SeekFrom::Current(0))?;
 
         let information = HeaderInformation::new(file, pos)?;
 
-        Ok(Header {
+        let mut header = Header {
             game_code,
             maker_code,
             disk_id,
             version,
             audio_streaming,
             stream_buffer_size,
-            title,
+            title: String::new(),
+            raw_title,
             debug_monitor_offset,
             debug_monitor_load_addr,
             dol_offset,
@@ -204,7 +281,75 @@ impl Header {
             user_length,
             unknown,
             information,
-        })
+        };
+        header.title = header.encoding().decode(&header.raw_title);
+        Ok(header)
+    }
+
+    // The encoding of the title and file names, which depends on the region
+    pub fn encoding(&self) -> TextEncoding {
+        TextEncoding::for_region(self.region())
+    }
+
+    // The game ID is the game code followed by the maker code, like GALE01
+    pub fn set_game_id(&mut self, game_id: &str) -> io::Result<()> {
+        check_code(game_id, GAME_CODE_SIZE + MAKER_CODE_SIZE, "game ID")?;
+        self.game_code = game_id[..GAME_CODE_SIZE].to_string();
+        self.maker_code = game_id[GAME_CODE_SIZE..].to_string();
+        Ok(())
+    }
+
+    pub fn set_maker_code(&mut self, maker_code: &str) -> io::Result<()> {
+        check_code(maker_code, MAKER_CODE_SIZE, "maker code")?;
+        self.maker_code = maker_code.to_string();
+        Ok(())
+    }
+
+    pub fn set_title(&mut self, title: &str) -> io::Result<()> {
+        let encoding = self.encoding();
+        let raw_title = encoding.encode(title).ok_or_else(|| io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("The title can't be written in {}", encoding.name()),
+        ))?;
+        // Leave room for the null byte at the end
+        if raw_title.len() >= GAME_NAME_SIZE || raw_title.contains(&0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("The title has to be less than {} bytes long", GAME_NAME_SIZE),
+            ));
+        }
+        self.title = title.to_string();
+        self.raw_title = raw_title;
+        Ok(())
+    }
+
+    // The region from the country code, or from the game code if the
+    // country code isn't one we know.
+    pub fn region(&self) -> Option<Region> {
+        Region::from_country_code(self.information.country_code)
+            .or_else(|| self.game_code.chars().last().and_then(Region::from_game_code_letter))
+    }
+
+    // Changes the country code and the last letter of the game code
+    pub fn set_region(&mut self, region: Region) {
+        self.information.country_code = region.country_code();
+        if self.game_code.len() == GAME_CODE_SIZE && self.game_code.is_ascii() {
+            self.game_code.truncate(GAME_CODE_SIZE - 1);
+            self.game_code.push(region.game_code_letter());
+        }
+        self.title = self.encoding().decode(&self.raw_title);
+    }
+
+    // Writes the header up to the end of the disk header information over the
+    // start of `writer`, leaving the rest of it as it is.
+    pub fn write_in_place<W>(&self, mut writer: W) -> io::Result<()>
+    where
+        W: Write + Seek,
+    {
+        let mut buf = Vec::with_capacity(GAME_HEADER_SIZE);
+        self.write(&mut buf)?;
+        writer.seek(SeekFrom::Start(0))?;
+        writer.write_all(&buf[..HEADER_INFORMATION_OFFSET + HEADER_INFORMATION_SIZE])
     }
 
     pub fn extract<R, W>(mut iso: R, output: W) -> io::Result<()>
@@ -233,8 +378,8 @@ impl Header {
         writer.write_u32::<BigEndian>(MAGIC_WORD)?;
 
         buf.resize(GAME_NAME_SIZE, 0);
-        writer.write_all(self.title.as_bytes())?;
-        buf.resize(GAME_NAME_SIZE - self.title.len(), 0);
+        writer.write_all(&self.raw_title)?;
+        buf.resize(GAME_NAME_SIZE - self.raw_title.len(), 0);
         writer.write_all(&buf[..])?;
 
         writer.write_u32::<BigEndian>(self.debug_monitor_offset)?;
@@ -266,10 +411,24 @@ impl Header {
     }
 }
 
+fn check_code(code: &str, len: usize, name: &str) -> io::Result<()> {
+    if code.len() == len && code.bytes().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit()) {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("The {} has to be {} capital letters or digits", name, len),
+        ))
+    }
+}
+
 impl Section for Header {
     fn print_info(&self, style: NumberStyle) {
         println!("Game ID: {}{}", self.game_code, self.maker_code);
         println!("Title: {}", self.title);
+        println!("Disc: {}", self.disk_id as usize + 1);
+        println!("Version: {}", self.version);
+        println!("Region: {}", self.region().map(Region::name).unwrap_or("unknown"));
         println!("DOL offset: {}", format_u64(self.dol_offset, style));
         println!("FST offset: {}", format_u64(self.fst_offset, style));
         println!("FST size: {} bytes", format_usize(self.fst_size, style));
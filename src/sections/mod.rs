@@ -1,7 +1,10 @@
 pub mod apploader;
+pub mod banner;
 pub mod dol;
 pub mod fst;
 pub mod header;
+pub mod rel;
+pub mod tgc;
 
 mod section;
 pub use self::section::Section;
@@ -0,0 +1,735 @@
+// Relocatable modules (`.rel` files), which games load on top of the main DOL.
+// http://wiki.tockdom.com/wiki/REL_(File_Format)
+
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
+
+use byteorder::{BigEndian, ByteOrder, ReadBytesExt};
+
+use ::{align, format_u64, format_usize, NumberStyle};
+use sections::dol::Dol;
+use sections::dol::segment::SegmentType;
+use sections::Section;
+
+pub const REL_EXTENSION: &str = "rel";
+
+// The size of the header for each version of the format
+const HEADER_SIZES: [usize; 3] = [0x40, 0x48, 0x4C];
+const SECTION_ENTRY_SIZE: u64 = 8;
+const IMPORT_ENTRY_SIZE: u64 = 8;
+const RELOCATION_ENTRY_SIZE: u64 = 8;
+
+// The BSS is aligned to this if the REL doesn't say otherwise
+const DEFAULT_BSS_ALIGNMENT: u64 = 32;
+
+// Set in a section's offset if it contains code
+const EXECUTABLE_FLAG: u32 = 1;
+
+// The main DOL is always module 0
+pub const DOL_MODULE_ID: u32 = 0;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RelocationType {
+    None,
+    Addr32,
+    Addr24,
+    Addr16,
+    Addr16Lo,
+    Addr16Hi,
+    Addr16Ha,
+    Addr14,
+    Addr14BrTaken,
+    Addr14BrNotTaken,
+    Rel24,
+    Rel14,
+    Rel14BrTaken,
+    Rel14BrNotTaken,
+    // These only tell the loader where the next relocation is
+    DolphinNop,
+    DolphinSection,
+    DolphinEnd,
+    DolphinMarkRef,
+}
+
+impl RelocationType {
+    pub fn from_u8(n: u8) -> Option<RelocationType> {
+        use self::RelocationType::*;
+        Some(match n {
+            0 => None,
+            1 => Addr32,
+            2 => Addr24,
+            3 => Addr16,
+            4 => Addr16Lo,
+            5 => Addr16Hi,
+            6 => Addr16Ha,
+            7 => Addr14,
+            8 => Addr14BrTaken,
+            9 => Addr14BrNotTaken,
+            10 => Rel24,
+            11 => Rel14,
+            12 => Rel14BrTaken,
+            13 => Rel14BrNotTaken,
+            201 => DolphinNop,
+            202 => DolphinSection,
+            203 => DolphinEnd,
+            204 => DolphinMarkRef,
+            _ => return Option::None,
+        })
+    }
+}
+
+impl fmt::Display for RelocationType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::RelocationType::*;
+        let name = match self {
+            None => "R_PPC_NONE",
+            Addr32 => "R_PPC_ADDR32",
+            Addr24 => "R_PPC_ADDR24",
+            Addr16 => "R_PPC_ADDR16",
+            Addr16Lo => "R_PPC_ADDR16_LO",
+            Addr16Hi => "R_PPC_ADDR16_HI",
+            Addr16Ha => "R_PPC_ADDR16_HA",
+            Addr14 => "R_PPC_ADDR14",
+            Addr14BrTaken => "R_PPC_ADDR14_BRTAKEN",
+            Addr14BrNotTaken => "R_PPC_ADDR14_BRNTAKEN",
+            Rel24 => "R_PPC_REL24",
+            Rel14 => "R_PPC_REL14",
+            Rel14BrTaken => "R_PPC_REL14_BRTAKEN",
+            Rel14BrNotTaken => "R_PPC_REL14_BRNTAKEN",
+            DolphinNop => "R_DOLPHIN_NOP",
+            DolphinSection => "R_DOLPHIN_SECTION",
+            DolphinEnd => "R_DOLPHIN_END",
+            DolphinMarkRef => "R_DOLPHIN_MRKREF",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct RelSection {
+    pub index: usize,
+    // Relative to the start of the REL. This is 0 for sections that
+    // aren't stored in the file, like the BSS.
+    pub offset: u64,
+    pub size: usize,
+    pub executable: bool,
+}
+
+impl RelSection {
+    pub fn is_bss(&self) -> bool {
+        self.offset == 0 && self.size > 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+}
+
+// A spot in this module to patch, and what to patch it with
+#[derive(Copy, Clone, Debug)]
+pub struct Relocation {
+    pub reloc_type: RelocationType,
+    // The section and offset in this module to patch
+    pub section: usize,
+    pub offset: u64,
+    // The section in the imported module that's being referred to. For the
+    // DOL, this is always 0, and `addend` is an absolute address.
+    pub target_section: usize,
+    pub addend: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Import {
+    pub module_id: u32,
+    pub relocations: Vec<Relocation>,
+}
+
+// Where a REL and its BSS are in memory
+#[derive(Copy, Clone, Debug)]
+pub struct LoadAddresses {
+    pub load_address: u64,
+    pub bss_address: u64,
+}
+
+// What happened when linking a REL
+#[derive(Debug, Default)]
+pub struct LinkReport {
+    pub relocations_applied: usize,
+    // The modules this one imports from that weren't given to `link`, so
+    // their relocations were left alone
+    pub missing_modules: Vec<u32>,
+}
+
+#[derive(Debug)]
+pub struct REL {
+    pub offset: u64,
+    pub size: usize,
+    pub module_id: u32,
+    pub version: u32,
+    pub name_offset: u64,
+    pub name_size: usize,
+    pub bss_size: usize,
+    pub bss_section: usize,
+    // Each of these is a section index and an offset in that section
+    pub prolog: (usize, u64),
+    pub epilog: (usize, u64),
+    pub unresolved: (usize, u64),
+    pub align: Option<u32>,
+    pub bss_align: Option<u32>,
+    pub fix_size: Option<u32>,
+    pub sections: Vec<RelSection>,
+    pub imports: Vec<Import>,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Invalid REL: {}", message))
+}
+
+impl REL {
+    // Reads the REL of `size` bytes at `offset`. RELs don't have any magic
+    // number, so this checks that everything in the header makes sense.
+    pub fn new<R>(mut file: R, offset: u64, size: usize) -> io::Result<REL>
+    where
+        R: Read + Seek,
+    {
+        file.seek(SeekFrom::Start(offset))?;
+        let module_id = file.read_u32::<BigEndian>()?;
+        let _next = file.read_u32::<BigEndian>()?;
+        let _prev = file.read_u32::<BigEndian>()?;
+        let section_count = file.read_u32::<BigEndian>()? as u64;
+        let section_info_offset = file.read_u32::<BigEndian>()? as u64;
+        let name_offset = file.read_u32::<BigEndian>()? as u64;
+        let name_size = file.read_u32::<BigEndian>()? as usize;
+        let version = file.read_u32::<BigEndian>()?;
+        let bss_size = file.read_u32::<BigEndian>()? as usize;
+        let relocation_offset = file.read_u32::<BigEndian>()? as u64;
+        let import_offset = file.read_u32::<BigEndian>()? as u64;
+        let import_size = file.read_u32::<BigEndian>()? as u64;
+        let prolog_section = file.read_u8()? as usize;
+        let epilog_section = file.read_u8()? as usize;
+        let unresolved_section = file.read_u8()? as usize;
+        let bss_section = file.read_u8()? as usize;
+        let prolog = file.read_u32::<BigEndian>()? as u64;
+        let epilog = file.read_u32::<BigEndian>()? as u64;
+        let unresolved = file.read_u32::<BigEndian>()? as u64;
+
+        if version == 0 || version as usize > HEADER_SIZES.len() {
+            return Err(invalid("unknown version"));
+        }
+        let header_size = HEADER_SIZES[version as usize - 1] as u64;
+        let in_file = |start: u64, len: u64| start + len <= size as u64;
+        if section_count == 0 ||
+            section_info_offset < header_size ||
+            !in_file(section_info_offset, section_count * SECTION_ENTRY_SIZE) ||
+            !in_file(import_offset, import_size) ||
+            import_size % IMPORT_ENTRY_SIZE != 0
+        {
+            return Err(invalid("the header is corrupt"));
+        }
+
+        let (align, bss_align) = if version >= 2 {
+            (Some(file.read_u32::<BigEndian>()?), Some(file.read_u32::<BigEndian>()?))
+        } else {
+            (None, None)
+        };
+        let fix_size = if version >= 3 {
+            Some(file.read_u32::<BigEndian>()?)
+        } else {
+            None
+        };
+
+        file.seek(SeekFrom::Start(offset + section_info_offset))?;
+        let mut sections = Vec::with_capacity(section_count as usize);
+        for index in 0..section_count as usize {
+            let section_offset = file.read_u32::<BigEndian>()?;
+            let section_size = file.read_u32::<BigEndian>()? as usize;
+            let s = RelSection {
+                index,
+                offset: (section_offset & !EXECUTABLE_FLAG) as u64,
+                size: section_size,
+                executable: section_offset & EXECUTABLE_FLAG != 0,
+            };
+            if s.offset != 0 && !in_file(s.offset, s.size as u64) {
+                return Err(invalid("a section goes past the end of the file"));
+            }
+            sections.push(s);
+        }
+
+        for &section in &[prolog_section, epilog_section, unresolved_section, bss_section] {
+            if section >= sections.len() {
+                return Err(invalid("the header refers to a section that doesn't exist"));
+            }
+        }
+
+        let mut imports = Vec::with_capacity((import_size / IMPORT_ENTRY_SIZE) as usize);
+        for i in 0..import_size / IMPORT_ENTRY_SIZE {
+            file.seek(SeekFrom::Start(offset + import_offset + i * IMPORT_ENTRY_SIZE))?;
+            let module_id = file.read_u32::<BigEndian>()?;
+            let list_offset = file.read_u32::<BigEndian>()? as u64;
+            if list_offset < relocation_offset || !in_file(list_offset, 0) {
+                return Err(invalid("an import's relocations are outside of the file"));
+            }
+            let relocations = read_relocations(
+                &mut file,
+                offset + list_offset,
+                offset + size as u64,
+                &sections,
+            )?;
+            imports.push(Import { module_id, relocations });
+        }
+
+        Ok(REL {
+            offset,
+            size,
+            module_id,
+            version,
+            name_offset,
+            name_size,
+            bss_size,
+            bss_section,
+            prolog: (prolog_section, prolog),
+            epilog: (epilog_section, epilog),
+            unresolved: (unresolved_section, unresolved),
+            align,
+            bss_align,
+            fix_size,
+            sections,
+            imports,
+        })
+    }
+
+    // Whether a file with this name is probably a REL
+    pub fn has_rel_extension(name: impl AsRef<str>) -> bool {
+        name.as_ref().rsplit('.').next()
+            .is_some_and(|e| e.eq_ignore_ascii_case(REL_EXTENSION))
+    }
+
+    pub fn section(&self, index: usize) -> Option<&RelSection> {
+        self.sections.get(index)
+    }
+
+    // Finds the section at `offset`, which is relative to the start of the ROM
+    pub fn section_at_offset(&self, offset: u64) -> Option<&RelSection> {
+        let offset = offset.checked_sub(self.offset)?;
+        self.sections.iter()
+            .find(|s| s.offset != 0 && s.offset <= offset && offset < s.offset + s.size as u64)
+    }
+
+    // The address a section will be at if the REL is loaded at `load_address`.
+    // The BSS is allocated separately, so its address is given by `bss_address`.
+    pub fn section_address(&self, index: usize, load_address: u64, bss_address: Option<u64>) -> Option<u64> {
+        let s = self.section(index)?;
+        if s.is_bss() {
+            bss_address
+        } else if s.is_empty() {
+            None
+        } else {
+            Some(load_address + s.offset)
+        }
+    }
+
+    // Converts a section and offset, like the prolog, into an address
+    pub fn resolve(&self, (section, offset): (usize, u64), load_address: u64, bss_address: Option<u64>) -> Option<u64> {
+        self.section_address(section, load_address, bss_address)
+            .map(|a| a + offset)
+    }
+
+    // Puts the BSS right after the rest of the REL, which is what
+    // happens when a game allocates them together.
+    pub fn default_addresses(&self, load_address: u64) -> LoadAddresses {
+        let bss_align = self.bss_align.filter(|&a| a > 0).map_or(DEFAULT_BSS_ALIGNMENT, |a| a as u64);
+        LoadAddresses {
+            load_address,
+            bss_address: align(load_address + self.size as u64, bss_align),
+        }
+    }
+
+    // Reads the whole REL, which is what gets loaded into memory
+    pub fn read_image<R>(&self, mut file: R) -> io::Result<Vec<u8>>
+    where
+        R: Read + Seek,
+    {
+        let mut image = vec![0; self.size];
+        file.seek(SeekFrom::Start(self.offset))?;
+        file.read_exact(&mut image)?;
+        Ok(image)
+    }
+
+    // Applies the relocations to `image`, which was read with `read_image`,
+    // like the game would when loading the REL at `addresses`. Imports from
+    // the DOL (module 0) use absolute addresses, and imports from other RELs
+    // are resolved using the RELs in `other_modules`.
+    pub fn link(
+        &self,
+        image: &mut [u8],
+        addresses: LoadAddresses,
+        other_modules: &[(&REL, LoadAddresses)],
+    ) -> io::Result<LinkReport> {
+        let mut report = LinkReport::default();
+
+        for import in &self.imports {
+            let module = if import.module_id == DOL_MODULE_ID {
+                None
+            } else if import.module_id == self.module_id {
+                Some((self, addresses))
+            } else {
+                match other_modules.iter().find(|(m, _)| m.module_id == import.module_id) {
+                    Some(&(m, a)) => Some((m, a)),
+                    None => {
+                        report.missing_modules.push(import.module_id);
+                        continue;
+                    },
+                }
+            };
+
+            for r in &import.relocations {
+                let target = match module {
+                    None => r.addend as u64,
+                    Some((m, a)) => m.section_address(r.target_section, a.load_address, Some(a.bss_address))
+                        .ok_or_else(|| invalid(&format!(
+                            "a relocation refers to an empty section in module {}",
+                            m.module_id,
+                        )))? + r.addend as u64,
+                };
+                self.apply_relocation(image, addresses, r, target)?;
+                report.relocations_applied += 1;
+            }
+        }
+
+        Ok(report)
+    }
+
+    fn apply_relocation(
+        &self,
+        image: &mut [u8],
+        addresses: LoadAddresses,
+        r: &Relocation,
+        target: u64,
+    ) -> io::Result<()> {
+        use self::RelocationType::*;
+
+        let section = self.sections[r.section];
+        let size = match r.reloc_type {
+            Addr32 | Addr24 | Rel24 | Addr14 | Addr14BrTaken | Addr14BrNotTaken |
+                Rel14 | Rel14BrTaken | Rel14BrNotTaken => 4,
+            Addr16 | Addr16Lo | Addr16Hi | Addr16Ha => 2,
+            _ => return Ok(()),
+        };
+        if section.is_bss() || r.offset + size > section.size as u64 {
+            return Err(invalid("a relocation is outside of its section"));
+        }
+
+        let position = (section.offset + r.offset) as usize;
+        let address = addresses.load_address + position as u64;
+        let target = target as u32;
+        let relative = target.wrapping_sub(address as u32);
+        let bytes = &mut image[position..position + size as usize];
+
+        let word = |bytes: &[u8], mask: u32, value: u32| {
+            (BigEndian::read_u32(bytes) & !mask) | (value & mask)
+        };
+        // Branch fields are sign extended, so `value` only fits if it's the
+        // same after being sign extended from the field's top bit
+        let check_range = |value: u32, bits: u32| {
+            if (((value << (32 - bits)) as i32) >> (32 - bits)) as u32 == value {
+                Ok(value)
+            } else {
+                Err(invalid("a relocation is out of range"))
+            }
+        };
+        match r.reloc_type {
+            Addr32 => BigEndian::write_u32(bytes, target),
+            Addr24 => {
+                let w = word(bytes, 0x03FF_FFFC, check_range(target, 26)?);
+                BigEndian::write_u32(bytes, w);
+            },
+            Rel24 => {
+                let w = word(bytes, 0x03FF_FFFC, check_range(relative, 26)?);
+                BigEndian::write_u32(bytes, w);
+            },
+            Addr14 | Addr14BrTaken | Addr14BrNotTaken => {
+                let w = word(bytes, 0xFFFC, check_range(target, 16)?);
+                BigEndian::write_u32(bytes, w);
+            },
+            Rel14 | Rel14BrTaken | Rel14BrNotTaken => {
+                let w = word(bytes, 0xFFFC, check_range(relative, 16)?);
+                BigEndian::write_u32(bytes, w);
+            },
+            Addr16 | Addr16Lo => BigEndian::write_u16(bytes, target as u16),
+            Addr16Hi => BigEndian::write_u16(bytes, (target >> 16) as u16),
+            // The low half is treated as signed, so this makes up for that
+            Addr16Ha => BigEndian::write_u16(bytes, (target.wrapping_add(0x8000) >> 16) as u16),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    // Makes a DOL out of a linked REL with a segment for each section, which
+    // is an easy way to turn it into an ELF. The prolog is the entry point.
+    pub fn to_dol(&self, image: &[u8], addresses: LoadAddresses) -> io::Result<Dol> {
+        let entry_point = self.resolve(self.prolog, addresses.load_address, Some(addresses.bss_address))
+            .unwrap_or(0);
+        let mut dol = Dol::new(entry_point);
+        for s in self.sections.iter().filter(|s| !s.is_empty() && !s.is_bss()) {
+            let seg_type = if s.executable { SegmentType::Text } else { SegmentType::Data };
+            let start = s.offset as usize;
+            let data = image[start..start + s.size].to_vec();
+            dol.add_segment(seg_type, addresses.load_address + s.offset, data)?;
+        }
+        if let Some(bss) = self.sections.iter().find(|s| s.is_bss()) {
+            dol.bss_address = addresses.bss_address;
+            dol.bss_size = bss.size;
+        }
+        Ok(dol)
+    }
+
+    pub fn relocation_count(&self) -> usize {
+        self.imports.iter().map(|i| i.relocations.len()).sum()
+    }
+
+    // Prints the same thing as `print_info`, along with where each section
+    // and function would be if the REL were loaded at `load_address`.
+    pub fn print_info_at(&self, style: NumberStyle, load_address: Option<u64>) {
+        println!("Offset: {}", format_u64(self.offset, style));
+        println!("Size: {} bytes", format_usize(self.size, style));
+        println!("Module ID: {}", self.module_id);
+        println!("Version: {}", self.version);
+        println!("BSS size: {} bytes", format_usize(self.bss_size, style));
+        if let Some(align) = self.align {
+            println!("Alignment: {}", format_u64(align as u64, style));
+        }
+        if let Some(bss_align) = self.bss_align {
+            println!("BSS alignment: {}", format_u64(bss_align as u64, style));
+        }
+        if let Some(fix_size) = self.fix_size {
+            println!("Fix size: {}", format_u64(fix_size as u64, style));
+        }
+        if let Some(load_address) = load_address {
+            println!("Load address: {}", format_u64(load_address, style));
+        }
+
+        let functions = [("Prolog", self.prolog), ("Epilog", self.epilog), ("Unresolved", self.unresolved)];
+        for &(name, location) in &functions {
+            print!("{}: section {} + {}", name, location.0, format_u64(location.1, style));
+            if let Some(addr) = load_address.and_then(|a| self.resolve(location, a, None)) {
+                print!(" ({})", format_u64(addr, style));
+            }
+            println!();
+        }
+
+        println!("Sections:");
+        for s in self.sections.iter().filter(|s| !s.is_empty()) {
+            println!();
+            println!("Section: {}", s.index);
+            if s.is_bss() {
+                println!("Type: bss");
+            } else {
+                println!("Type: {}", if s.executable { "text" } else { "data" });
+                println!("Offset: {}", format_u64(self.offset + s.offset, style));
+            }
+            println!("Size: {}", format_usize(s.size, style));
+            if let Some(addr) = load_address.and_then(|a| self.section_address(s.index, a, None)) {
+                println!("Address: {}", format_u64(addr, style));
+            }
+        }
+
+        println!();
+        println!("Imports:");
+        for import in &self.imports {
+            let module = if import.module_id == DOL_MODULE_ID {
+                "the DOL".to_string()
+            } else if import.module_id == self.module_id {
+                "itself".to_string()
+            } else {
+                format!("module {}", import.module_id)
+            };
+            println!("  {} relocations from {}", import.relocations.len(), module);
+        }
+    }
+}
+
+// Reads relocations until an R_DOLPHIN_END. The offset of each relocation is
+// relative to the previous one, and R_DOLPHIN_SECTION moves to the start of
+// another section.
+fn read_relocations<R>(
+    mut file: R,
+    offset: u64,
+    end: u64,
+    sections: &[RelSection],
+) -> io::Result<Vec<Relocation>>
+where
+    R: Read + Seek,
+{
+    file.seek(SeekFrom::Start(offset))?;
+    let mut relocations = Vec::new();
+    let mut section = 0;
+    let mut position = 0;
+
+    for entry_offset in (offset..).step_by(RELOCATION_ENTRY_SIZE as usize) {
+        if entry_offset + RELOCATION_ENTRY_SIZE > end {
+            return Err(invalid("a list of relocations doesn't end"));
+        }
+        let delta = file.read_u16::<BigEndian>()? as u64;
+        let reloc_type = file.read_u8()?;
+        let target_section = file.read_u8()? as usize;
+        let addend = file.read_u32::<BigEndian>()?;
+
+        let reloc_type = RelocationType::from_u8(reloc_type)
+            .ok_or_else(|| invalid(&format!("unknown relocation type {}", reloc_type)))?;
+        position += delta;
+        match reloc_type {
+            RelocationType::DolphinEnd => break,
+            RelocationType::DolphinSection => {
+                if target_section >= sections.len() {
+                    return Err(invalid("a relocation refers to a section that doesn't exist"));
+                }
+                section = target_section;
+                position = 0;
+            },
+            RelocationType::DolphinNop | RelocationType::None => (),
+            _ => relocations.push(Relocation {
+                reloc_type,
+                section,
+                offset: position,
+                target_section,
+                addend,
+            }),
+        }
+    }
+    Ok(relocations)
+}
+
+impl Section for REL {
+    fn print_info(&self, style: NumberStyle) {
+        self.print_info_at(style, None);
+    }
+
+    fn start(&self) -> u64 {
+        self.offset
+    }
+
+    fn size(&self) -> usize {
+        self.size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    const LOAD_ADDRESS: u64 = 0x8050_0000;
+    // Where the code section is in the REL
+    const CODE_OFFSET: usize = 0x60;
+
+    // Builds a REL with an empty section, 0x20 bytes of code, 8 bytes of
+    // data, and a BSS. It imports from the DOL with `relocations`, which are
+    // the offset from the last one, the type, the section, and the addend.
+    fn build_rel(relocations: &[(u16, u8, u8, u32)]) -> Vec<u8> {
+        let mut rel = vec![0; 0x90];
+        let header: [u32; 12] = [1, 0, 0, 4, 0x40, 0, 0, 1, 0x10, 0x90, 0x88, 8];
+        for (i, &value) in header.iter().enumerate() {
+            BigEndian::write_u32(&mut rel[i * 4..], value);
+        }
+        // The prolog, epilog, unresolved, and BSS sections
+        rel[0x30..0x34].copy_from_slice(&[1, 1, 1, 3]);
+
+        let sections = [(0, 0), (CODE_OFFSET as u32 | EXECUTABLE_FLAG, 0x20), (0x80, 8), (0, 0x10)];
+        for (i, &(offset, size)) in sections.iter().enumerate() {
+            BigEndian::write_u32(&mut rel[0x40 + i * 8..], offset);
+            BigEndian::write_u32(&mut rel[0x44 + i * 8..], size);
+        }
+        // bl, lis r3, addi r3, beq
+        let code = [0x4800_0001, 0x3C60_0000, 0x3863_0000, 0x4182_0000];
+        for (i, &instruction) in code.iter().enumerate() {
+            BigEndian::write_u32(&mut rel[CODE_OFFSET + i * 4..], instruction);
+        }
+        // One import, from the DOL
+        BigEndian::write_u32(&mut rel[0x8C..], 0x90);
+
+        for &(delta, reloc_type, section, addend) in relocations {
+            let mut entry = [0; 8];
+            BigEndian::write_u16(&mut entry[0..], delta);
+            entry[2] = reloc_type;
+            entry[3] = section;
+            BigEndian::write_u32(&mut entry[4..], addend);
+            rel.extend_from_slice(&entry);
+        }
+        rel.extend_from_slice(&[0, 0, 203, 0, 0, 0, 0, 0]);
+        rel
+    }
+
+    fn read_rel(data: &[u8]) -> REL {
+        REL::new(Cursor::new(data), 0, data.len()).unwrap()
+    }
+
+    // Links a REL with `relocations` at `LOAD_ADDRESS`, and returns its code
+    fn link(relocations: &[(u16, u8, u8, u32)]) -> io::Result<Vec<u8>> {
+        let data = build_rel(relocations);
+        let rel = read_rel(&data);
+        let mut image = rel.read_image(Cursor::new(&data))?;
+        rel.link(&mut image, rel.default_addresses(LOAD_ADDRESS), &[])?;
+        Ok(image[CODE_OFFSET..CODE_OFFSET + 0x20].to_vec())
+    }
+
+    #[test]
+    fn relocation_offsets() {
+        let rel = read_rel(&build_rel(&[
+            (0, 202, 1, 0),
+            (6, 6, 0, 0),
+            (4, 4, 0, 0),
+            // Only moves the next relocation along
+            (2, 201, 0, 0),
+            (0, 11, 0, 0),
+            // The offset starts over in the new section
+            (0x40, 202, 2, 0),
+            (4, 1, 0, 0),
+        ]));
+        let relocations: Vec<_> = rel.imports[0].relocations.iter()
+            .map(|r| (r.reloc_type, r.section, r.offset))
+            .collect();
+        assert_eq!(relocations, [
+            (RelocationType::Addr16Ha, 1, 6),
+            (RelocationType::Addr16Lo, 1, 10),
+            (RelocationType::Rel14, 1, 12),
+            (RelocationType::Addr32, 2, 4),
+        ]);
+    }
+
+    #[test]
+    fn link_addresses() {
+        let code = link(&[
+            (0, 202, 1, 0),
+            (0, 10, 0, 0x8040_0000),
+            // The low half is negative, so the high half is rounded up
+            (6, 6, 0, 0x8001_8000),
+            (4, 4, 0, 0x8001_8000),
+            (2, 11, 0, LOAD_ADDRESS as u32 + CODE_OFFSET as u32 + 0xC + 0x100),
+            (4, 1, 0, 0x8000_1234),
+        ]).unwrap();
+        let word = |i: usize| BigEndian::read_u32(&code[i * 4..]);
+        // 0x80400000 - 0x80500060, with the opcode and link bit kept
+        assert_eq!(word(0), 0x4BEF_FFA1);
+        assert_eq!(word(1), 0x3C60_8002);
+        assert_eq!(word(2), 0x3863_8000);
+        assert_eq!(word(3), 0x4182_0100);
+        assert_eq!(word(4), 0x8000_1234);
+    }
+
+    #[test]
+    fn link_out_of_range() {
+        let address = LOAD_ADDRESS as u32 + CODE_OFFSET as u32;
+        // 32 MiB back is as far as a branch can go
+        assert!(link(&[(0, 202, 1, 0), (0, 10, 0, address - 0x0200_0000)]).is_ok());
+        assert!(link(&[(0, 202, 1, 0), (0, 10, 0, address - 0x0200_0004)]).is_err());
+        assert!(link(&[(0, 202, 1, 0), (0, 10, 0, address + 0x0200_0000)]).is_err());
+        // Absolute branches can't reach the usual addresses
+        assert!(link(&[(0, 202, 1, 0), (0, 2, 0, 0x8000_1000)]).is_err());
+
+        let address = address + 0xC;
+        assert!(link(&[(0, 202, 1, 0), (0xC, 11, 0, address + 0x7FFC)]).is_ok());
+        assert!(link(&[(0, 202, 1, 0), (0xC, 11, 0, address + 0x8000)]).is_err());
+        assert!(link(&[(0, 202, 1, 0), (0xC, 11, 0, address - 0x8000)]).is_ok());
+    }
+}
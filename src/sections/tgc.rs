@@ -0,0 +1,227 @@
+// TGC files are whole games packed into a single file, found on demo and
+// kiosk discs. After the TGC header comes what is basically a small disc
+// image, but the offsets in its header and FST are relative to a different
+// "virtual" file area, so they need to be adjusted before it can be read
+// like a regular ROM. This is based on Dolphin's TGCBlob.cpp.
+
+use std::cmp::min;
+use std::io::{self, Read, Seek, SeekFrom};
+
+use byteorder::{BigEndian, ByteOrder, ReadBytesExt};
+
+use ::{
+    format_u64,
+    NumberStyle,
+};
+use container::seek_position;
+use sections::dol::DOL_OFFSET_OFFSET;
+use sections::Section;
+
+pub const TGC_MAGIC: u32 = 0xAE0F_38A2;
+
+const FST_ENTRY_SIZE: usize = 12;
+
+#[derive(Debug)]
+pub struct TGCHeader {
+    pub offset: u64,
+    pub header_size: u32,
+    pub disc_header_area_size: u32,
+    pub fst_real_offset: u32,
+    pub fst_size: u32,
+    pub fst_max_size: u32,
+    pub dol_real_offset: u32,
+    pub dol_size: u32,
+    pub file_area_real_offset: u32,
+    pub banner_real_offset: u32,
+    pub banner_size: u32,
+    pub file_area_virtual_offset: u32,
+}
+
+impl TGCHeader {
+    pub fn new<R>(mut file: R, offset: u64) -> io::Result<TGCHeader>
+    where
+        R: Read + Seek,
+    {
+        file.seek(SeekFrom::Start(offset))?;
+        if file.read_u32::<BigEndian>()? != TGC_MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a TGC file"));
+        }
+        file.seek(SeekFrom::Current(4))?; // unknown
+
+        let header_size = file.read_u32::<BigEndian>()?;
+        let disc_header_area_size = file.read_u32::<BigEndian>()?;
+        let fst_real_offset = file.read_u32::<BigEndian>()?;
+        let fst_size = file.read_u32::<BigEndian>()?;
+        let fst_max_size = file.read_u32::<BigEndian>()?;
+        let dol_real_offset = file.read_u32::<BigEndian>()?;
+        let dol_size = file.read_u32::<BigEndian>()?;
+        let file_area_real_offset = file.read_u32::<BigEndian>()?;
+        file.seek(SeekFrom::Current(4))?; // unknown
+        let banner_real_offset = file.read_u32::<BigEndian>()?;
+        let banner_size = file.read_u32::<BigEndian>()?;
+        let file_area_virtual_offset = file.read_u32::<BigEndian>()?;
+
+        if fst_real_offset < header_size || dol_real_offset < header_size {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid TGC header"));
+        }
+
+        Ok(TGCHeader {
+            offset,
+            header_size,
+            disc_header_area_size,
+            fst_real_offset,
+            fst_size,
+            fst_max_size,
+            dol_real_offset,
+            dol_size,
+            file_area_real_offset,
+            banner_real_offset,
+            banner_size,
+            file_area_virtual_offset,
+        })
+    }
+
+    // How far the files are from where the TGC's FST says they are,
+    // relative to the start of the embedded disc image.
+    pub fn file_area_shift(&self) -> i64 {
+        self.file_area_real_offset as i64 -
+            self.file_area_virtual_offset as i64 -
+            self.header_size as i64
+    }
+
+    // Where the DOL and FST are in the embedded disc image
+    pub fn dol_offset(&self) -> u64 {
+        (self.dol_real_offset - self.header_size) as u64
+    }
+
+    pub fn fst_offset(&self) -> u64 {
+        (self.fst_real_offset - self.header_size) as u64
+    }
+}
+
+impl Section for TGCHeader {
+    fn print_info(&self, style: NumberStyle) {
+        println!("Offset: {}", format_u64(self.offset, style));
+        println!("Header size: {} bytes", format_u64(self.header_size as u64, style));
+        println!(
+            "Disc header area size: {} bytes",
+            format_u64(self.disc_header_area_size as u64, style),
+        );
+        println!("FST offset: {}", format_u64(self.fst_real_offset as u64, style));
+        println!("FST size: {} bytes", format_u64(self.fst_size as u64, style));
+        println!("DOL offset: {}", format_u64(self.dol_real_offset as u64, style));
+        println!("DOL size: {} bytes", format_u64(self.dol_size as u64, style));
+        println!("Banner offset: {}", format_u64(self.banner_real_offset as u64, style));
+        println!("Banner size: {} bytes", format_u64(self.banner_size as u64, style));
+        println!(
+            "File area offset: {} (virtual offset: {})",
+            format_u64(self.file_area_real_offset as u64, style),
+            format_u64(self.file_area_virtual_offset as u64, style),
+        );
+    }
+
+    fn start(&self) -> u64 {
+        self.offset
+    }
+
+    fn size(&self) -> usize {
+        self.header_size as usize
+    }
+}
+
+// Presents the disc image embedded in a TGC file like a regular ROM, with the
+// DOL and FST offsets in its header and the file offsets in its FST fixed.
+pub struct TGCReader<R> {
+    reader: R,
+    header: TGCHeader,
+    size: u64,
+    // The FST, with the file offsets already adjusted
+    fst: Vec<u8>,
+    position: u64,
+}
+
+impl<R: Read + Seek> TGCReader<R> {
+    // `offset` is where the TGC file starts in `reader`, and `size` is its size.
+    pub fn new(mut reader: R, offset: u64, size: u64) -> io::Result<TGCReader<R>> {
+        let header = TGCHeader::new(&mut reader, offset)?;
+        if size < header.header_size as u64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "TGC file is too small"));
+        }
+
+        let mut fst = Vec::with_capacity(header.fst_size as usize);
+        reader.seek(SeekFrom::Start(offset + header.fst_real_offset as u64))?;
+        (&mut reader).take(header.fst_size as u64).read_to_end(&mut fst)?;
+        if fst.len() < FST_ENTRY_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid TGC FST"));
+        }
+
+        // The root entry's size field is the number of entries
+        let entry_count = BigEndian::read_u32(&fst[8..]) as usize;
+        let shift = header.file_area_shift();
+        for entry in fst.chunks_mut(FST_ENTRY_SIZE).take(entry_count) {
+            let is_file = entry[0] == 0;
+            if is_file && entry.len() == FST_ENTRY_SIZE {
+                let file_offset = BigEndian::read_u32(&entry[4..]) as i64 + shift;
+                BigEndian::write_u32(&mut entry[4..], file_offset as u32);
+            }
+        }
+
+        Ok(TGCReader {
+            reader,
+            size: size - header.header_size as u64,
+            header,
+            fst,
+            position: 0,
+        })
+    }
+
+    pub fn header(&self) -> &TGCHeader {
+        &self.header
+    }
+}
+
+// Replaces the part of `buf` (which was read from `buf_offset`)
+// that overlaps `data` (which belongs at `data_offset`) with `data`.
+fn overlay(buf: &mut [u8], buf_offset: u64, data: &[u8], data_offset: u64) {
+    let start = buf_offset.max(data_offset);
+    let end = (buf_offset + buf.len() as u64).min(data_offset + data.len() as u64);
+    if start < end {
+        let buf_start = (start - buf_offset) as usize;
+        let data_start = (start - data_offset) as usize;
+        let len = (end - start) as usize;
+        buf[buf_start..buf_start + len]
+            .copy_from_slice(&data[data_start..data_start + len]);
+    }
+}
+
+impl<R: Read + Seek> Read for TGCReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.size || buf.is_empty() {
+            return Ok(0);
+        }
+        let len = min(buf.len() as u64, self.size - self.position) as usize;
+        let buf = &mut buf[..len];
+
+        let data_start = self.header.offset + self.header.header_size as u64;
+        self.reader.seek(SeekFrom::Start(data_start + self.position))?;
+        let bytes_read = self.reader.read(buf)?;
+        let buf = &mut buf[..bytes_read];
+
+        // The FST offset comes right after the DOL offset in the header
+        let mut offsets = [0; 8];
+        BigEndian::write_u32(&mut offsets[..4], self.header.dol_offset() as u32);
+        BigEndian::write_u32(&mut offsets[4..], self.header.fst_offset() as u32);
+        overlay(buf, self.position, &offsets, DOL_OFFSET_OFFSET);
+        overlay(buf, self.position, &self.fst, self.header.fst_offset());
+
+        self.position += bytes_read as u64;
+        Ok(bytes_read)
+    }
+}
+
+impl<R: Read + Seek> Seek for TGCReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.position = seek_position(self.position, self.size, pos)?;
+        Ok(self.position)
+    }
+}
@@ -0,0 +1,168 @@
+// Symbol maps, which give names to the functions and variables in a DOL.
+// This reads both the link maps CodeWarrior generates and the `.map` files
+// that Dolphin saves, which look like this:
+//
+// .text section layout
+// 80003100 00000044 80003100 0 __start
+// 80003144 00000020 80003144 0 __init_registers
+//
+// .data section layout
+// 80004000 00000010 80004000 0 some_table
+//
+// CodeWarrior's maps use the same layout, but the first column is the offset
+// into the section, and there's an object file name after the symbol name.
+// Newer versions of the linker also add a file offset before the alignment.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+#[derive(Clone, Debug)]
+pub struct Symbol {
+    pub address: u64,
+    pub size: u32,
+    pub name: String,
+    // The section the symbol was listed under, like `.text` or `.sdata`
+    pub section: String,
+}
+
+impl Symbol {
+    pub fn is_function(&self) -> bool {
+        self.section.starts_with(".text") || self.section.starts_with(".init")
+    }
+
+    pub fn contains_addr(&self, mem_addr: u64) -> bool {
+        self.address <= mem_addr && mem_addr < self.address + self.size.max(1) as u64
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SymbolMap {
+    // Sorted by address
+    symbols: Vec<Symbol>,
+}
+
+impl SymbolMap {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<SymbolMap> {
+        SymbolMap::parse(BufReader::new(File::open(path)?))
+    }
+
+    pub fn parse(reader: impl BufRead) -> io::Result<SymbolMap> {
+        let mut symbols = Vec::new();
+        let mut section = String::new();
+
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.ends_with("section layout") {
+                section = line.split_whitespace().next().unwrap_or("").to_string();
+                continue;
+            }
+
+            let columns: Vec<_> = line.split_whitespace().collect();
+            if columns.len() < 5 {
+                continue;
+            }
+            // If there's a file offset, it's 8 digits, whereas the
+            // alignment that would be there otherwise is only 1 or 2.
+            let name = if columns.len() >= 6 && columns[3].len() == 8 {
+                columns[5]
+            } else {
+                columns[4]
+            };
+            // CodeWarrior lists the section each object file
+            // contributes, which aren't really symbols.
+            if name == section {
+                continue;
+            }
+
+            let size = u32::from_str_radix(columns[1], 16);
+            let address = u64::from_str_radix(columns[2], 16);
+            if let (Ok(size), Ok(address)) = (size, address) {
+                symbols.push(Symbol {
+                    address,
+                    size,
+                    name: name.to_string(),
+                    section: section.clone(),
+                });
+            }
+        }
+
+        symbols.sort_by_key(|s| s.address);
+        Ok(SymbolMap { symbols })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|s| s.name == name)
+    }
+
+    // Finds the symbol that `mem_addr` is in. Symbols without a size only
+    // contain their own address.
+    pub fn symbol_at_addr(&self, mem_addr: u64) -> Option<&Symbol> {
+        let end = self.symbols.partition_point(|s| s.address <= mem_addr);
+        self.symbols[..end].iter().rev().find(|s| s.contains_addr(mem_addr))
+    }
+
+    // Describes an address relative to the symbol it's in, like `main+0x1c`
+    pub fn describe_addr(&self, mem_addr: u64) -> Option<String> {
+        self.symbol_at_addr(mem_addr).map(|s| match mem_addr - s.address {
+            0 => s.name.clone(),
+            offset => format!("{}+{:#x}", s.name, offset),
+        })
+    }
+
+    pub fn insert(&mut self, symbol: Symbol) {
+        let i = self.symbols.partition_point(|s| s.address <= symbol.address);
+        self.symbols.insert(i, symbol);
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Symbol> {
+        self.symbols.iter()
+            .position(|s| s.name == name)
+            .map(|i| self.symbols.remove(i))
+    }
+
+    pub fn rename(&mut self, name: &str, new_name: impl Into<String>) -> bool {
+        match self.symbols.iter_mut().find(|s| s.name == name) {
+            Some(s) => {
+                s.name = new_name.into();
+                true
+            },
+            None => false,
+        }
+    }
+
+    // Writes the symbols in the format Dolphin saves and loads them in. The
+    // sections are written in the order they first appear in memory.
+    pub fn write_dolphin(&self, mut writer: impl Write) -> io::Result<()> {
+        let mut sections: Vec<&str> = Vec::new();
+        for s in &self.symbols {
+            if !sections.contains(&s.section.as_str()) {
+                sections.push(&s.section);
+            }
+        }
+
+        for (i, section) in sections.into_iter().enumerate() {
+            if i > 0 {
+                writeln!(writer)?;
+            }
+            writeln!(writer, "{} section layout", section)?;
+            for s in self.symbols.iter().filter(|s| s.section == section) {
+                writeln!(writer, "{:08x} {:08x} {:08x} 0 {}", s.address, s.size, s.address, s.name)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,49 @@
+// The text in a ROM, like its title and file names, is in Shift-JIS for
+// Japanese games and Windows-1252 for everything else. Neither is UTF-8, so
+// the bytes are kept around alongside the decoded text wherever it needs to
+// be written back exactly as it was.
+
+use encoding_rs::{Encoding, SHIFT_JIS, WINDOWS_1252};
+
+use sections::header::Region;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TextEncoding {
+    ShiftJis,
+    Windows1252,
+}
+
+impl TextEncoding {
+    pub fn for_region(region: Option<Region>) -> TextEncoding {
+        match region {
+            Some(Region::NtscJ) => TextEncoding::ShiftJis,
+            _ => TextEncoding::Windows1252,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        self.encoding().name()
+    }
+
+    fn encoding(self) -> &'static Encoding {
+        match self {
+            TextEncoding::ShiftJis => SHIFT_JIS,
+            TextEncoding::Windows1252 => WINDOWS_1252,
+        }
+    }
+
+    // Bytes that aren't valid in this encoding are replaced with U+FFFD
+    pub fn decode(self, bytes: &[u8]) -> String {
+        self.encoding().decode_without_bom_handling(bytes).0.into_owned()
+    }
+
+    // Returns `None` if some of `text` can't be represented in this encoding
+    pub fn encode(self, text: &str) -> Option<Vec<u8>> {
+        let (bytes, _, had_errors) = self.encoding().encode(text);
+        if had_errors {
+            None
+        } else {
+            Some(bytes.into_owned())
+        }
+    }
+}